@@ -0,0 +1,225 @@
+use std::io::{self, BufRead, Write};
+
+use colored::Colorize;
+
+use crate::steelseries_core::{
+    set_calibration, Calibration, Color, ColorMatrix, DeviceProperty, Error, PropertyKind,
+    PropertyValue, Result, SteelseriesDevice, TaskOptions, IDENTITY_MATRIX,
+};
+
+/// Colours shown while calibrating, white first since it shows all channels at once
+const REFERENCES: [(&str, (u8, u8, u8)); 6] = [
+    ("white", (0xff, 0xff, 0xff)),
+    ("red", (0xff, 0x00, 0x00)),
+    ("green", (0x00, 0xff, 0x00)),
+    ("blue", (0x00, 0x00, 0xff)),
+    ("orange", (0xff, 0xa5, 0x00)),
+    ("teal", (0x00, 0x80, 0x80)),
+];
+
+/// How much a gain changes with each `+` or `-`
+const STEP: f64 = 0.02;
+
+const CHANNELS: [&str; 3] = ["r", "g", "b"];
+
+enum Command {
+    /// Adds to the gain of a channel
+    Nudge(usize, f64),
+    /// Sets the gain of a channel
+    Set(usize, f64),
+    Next,
+    Previous,
+    Reset,
+    Save,
+    Quit,
+}
+
+/// Lets the user match the colours of `device` to those of the terminal by adjusting the
+/// red, green and blue gains of its calibration, while reference colours are shown on
+/// `property` or on the first colour property of the device
+pub fn calibrate(
+    device: &dyn SteelseriesDevice,
+    property: Option<DeviceProperty>,
+    options: &TaskOptions,
+) -> Result<()> {
+    let property = match property {
+        Some(property) => device.supports_capability(property).copied(),
+        None => device
+            .enumerate_capabilities()
+            .find(|c| matches!(c.value_kind(), PropertyKind::Color | PropertyKind::Gradient))
+            .copied(),
+    };
+    let property = match property {
+        Some(property) => property,
+        None => {
+            crate::OUTPUT
+                .error(format!("{} has no such colour property", device.get_name()).as_str());
+            return Err(Error::InvalidCapability);
+        }
+    };
+
+    // Reference colours are only shown, never saved to the device memory
+    let options = TaskOptions {
+        save: false,
+        ..*options
+    };
+    let path = Calibration::user_path()?;
+    let mut calibration = Calibration::load(&path)?;
+    let previous = device.read_property(property).ok();
+
+    let adjusted = adjust(device, property, &calibration, &options);
+    if let Ok(Some(matrix)) = adjusted {
+        calibration.set(device, matrix);
+    }
+    set_calibration(calibration.clone());
+
+    // The device shows what it showed before, with the calibration in effect from now on,
+    // however adjusting ended
+    let restored = match previous {
+        Some(previous) => device.change_property(property, previous, &options),
+        None => Ok(()),
+    };
+    let saved = adjusted?.is_some();
+    restored?;
+
+    if saved {
+        calibration.save(&path)?;
+        crate::OUTPUT.success(
+            format!("Saved the calibration of {} to {}", device.get_name(), path.display())
+                .as_str(),
+        );
+    }
+
+    Ok(())
+}
+
+/// Shows reference colours on `device` while the user adjusts its gains. Returns the
+/// matrix to save, or `None` when the user quits without saving.
+fn adjust(
+    device: &dyn SteelseriesDevice,
+    property: DeviceProperty,
+    calibration: &Calibration,
+    options: &TaskOptions,
+) -> Result<Option<ColorMatrix>> {
+    let mut matrix = calibration.get(device).unwrap_or(IDENTITY_MATRIX);
+
+    println!(
+        "Adjust the gains until {} shows the colour of the swatch.\n\
+         r+, g- or b++ nudge a gain, r 0.8 sets it, enter or n shows the next colour, p the \
+         previous one,\nreset clears the gains, s saves them and q quits without saving.",
+        device.get_name()
+    );
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut index = 0;
+    loop {
+        let (name, rgb) = REFERENCES[index];
+        let reference = Color::from(rgb);
+        show(device, property, &reference, calibration, matrix, options)?;
+
+        print!(
+            "{} {:<8} R {:.2}  G {:.2}  B {:.2} > ",
+            "      ".on_truecolor(rgb.0, rgb.1, rgb.2),
+            name,
+            matrix[0][0],
+            matrix[1][1],
+            matrix[2][2]
+        );
+        io::stdout().flush()?;
+
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => return Ok(None),
+        };
+        match parse_command(&line) {
+            Some(Command::Nudge(channel, by)) => {
+                matrix[channel][channel] = (matrix[channel][channel] + by).clamp(0.0, 1.0)
+            }
+            Some(Command::Set(channel, gain)) => matrix[channel][channel] = gain.clamp(0.0, 1.0),
+            Some(Command::Next) => index = (index + 1) % REFERENCES.len(),
+            Some(Command::Previous) => index = (index + REFERENCES.len() - 1) % REFERENCES.len(),
+            Some(Command::Reset) => matrix = IDENTITY_MATRIX,
+            Some(Command::Save) => return Ok(Some(matrix)),
+            Some(Command::Quit) => return Ok(None),
+            None => crate::OUTPUT.warn(format!("Unknown command {}", line.trim()).as_str()),
+        }
+    }
+}
+
+/// Forgets the calibration of `device`
+pub fn reset(device: &dyn SteelseriesDevice) -> Result<()> {
+    let path = Calibration::user_path()?;
+    let mut calibration = Calibration::load(&path)?;
+    if calibration.remove(device) {
+        calibration.save(&path)?;
+        crate::OUTPUT.success(format!("Removed the calibration of {}", device.get_name()).as_str());
+    } else {
+        crate::OUTPUT.warn(format!("{} is not calibrated", device.get_name()).as_str());
+    }
+
+    Ok(())
+}
+
+/// Shows `reference` on `device` as calibrated by `matrix`
+fn show(
+    device: &dyn SteelseriesDevice,
+    property: DeviceProperty,
+    reference: &Color,
+    calibration: &Calibration,
+    matrix: ColorMatrix,
+    options: &TaskOptions,
+) -> Result<()> {
+    let mut preview = calibration.clone();
+    preview.set(device, matrix);
+    set_calibration(preview);
+
+    device.change_property(property, PropertyValue::Color(reference.clone()), options)
+}
+
+fn parse_command(line: &str) -> Option<Command> {
+    let line = line.trim().to_lowercase();
+    match line.as_str() {
+        "" | "n" | "next" => return Some(Command::Next),
+        "p" | "previous" => return Some(Command::Previous),
+        "reset" => return Some(Command::Reset),
+        "s" | "save" => return Some(Command::Save),
+        "q" | "quit" => return Some(Command::Quit),
+        _ => (),
+    }
+
+    let channel = CHANNELS.iter().position(|c| line.starts_with(c))?;
+    let rest = line[1..].trim();
+    if !rest.is_empty() && rest.chars().all(|c| c == '+') {
+        Some(Command::Nudge(channel, STEP * rest.len() as f64))
+    } else if !rest.is_empty() && rest.chars().all(|c| c == '-') {
+        Some(Command::Nudge(channel, -STEP * rest.len() as f64))
+    } else {
+        rest.parse::<f64>()
+            .ok()
+            .filter(|gain| gain.is_finite())
+            .map(|gain| Command::Set(channel, gain))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_command, Command, STEP};
+
+    #[test]
+    fn commands() {
+        assert!(matches!(parse_command("r+"), Some(Command::Nudge(0, by)) if by == STEP));
+        assert!(matches!(parse_command(" G-- "), Some(Command::Nudge(1, by)) if by == -2.0 * STEP));
+        assert!(matches!(parse_command("b 0.8"), Some(Command::Set(2, gain)) if gain == 0.8));
+        assert!(matches!(parse_command(""), Some(Command::Next)));
+        assert!(matches!(parse_command("p"), Some(Command::Previous)));
+        assert!(matches!(parse_command("reset"), Some(Command::Reset)));
+        assert!(matches!(parse_command("s"), Some(Command::Save)));
+        assert!(matches!(parse_command("quit"), Some(Command::Quit)));
+
+        assert!(parse_command("r").is_none());
+        assert!(parse_command("r+-").is_none());
+        assert!(parse_command("g inf").is_none());
+        assert!(parse_command("x+").is_none());
+    }
+}
@@ -1,7 +1,175 @@
 use crate::steelseries_core::{
-    Result, {DeviceProperty, FromCode, SteelseriesDevice}, TaskOptions,
+    support::DevicePool, DeviceProperty, Error, FromCode, PropertyValue, Result, TaskOptions,
+    Transaction,
 };
 
-pub fn change(device: &dyn SteelseriesDevice, prop: &str, value: &str, options: &TaskOptions) -> Result<()> {
-    device.change_property(DeviceProperty::from_code(prop), value, options)
+/// Applies a batch of changes given as `device prop=value... [device prop=value...]`, all
+/// together or not at all. Assignments can also name their device, as `device.prop=value`,
+/// and a single change can be given as `device prop value`.
+pub fn change_all(pool: &DevicePool, args: &[&str], options: &TaskOptions) -> Result<()> {
+    let mut transaction = Transaction::new();
+    for (spec, prop, value) in parse_batch(args)? {
+        let device = pool.lookup(spec).inspect_err(|e| {
+            if let Error::NoDevice = e {
+                crate::OUTPUT.error(format!("No device matching {}", spec).as_str());
+            }
+        })?;
+        let property = parse_property(prop)?;
+        transaction.add(device, property, parse_value(property, value)?);
+    }
+
+    apply(&transaction, options)
+}
+
+/// Applies `transaction`, telling when devices were rolled back after a failure
+pub fn apply(transaction: &Transaction, options: &TaskOptions) -> Result<()> {
+    if let Err(e) = transaction.apply(options) {
+        if transaction.len() > 1 || options.save {
+            crate::OUTPUT.error("Changes failed, devices were rolled back to their previous values");
+        }
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+pub fn parse_property(prop: &str) -> Result<DeviceProperty> {
+    DeviceProperty::from_code(prop).inspect_err(|_| {
+        crate::OUTPUT.error(format!("Unknown property {}", prop).as_str());
+    })
+}
+
+pub fn parse_value(property: DeviceProperty, value: &str) -> Result<PropertyValue> {
+    match property.value_kind().parse(value) {
+        Ok(value) => Ok(value),
+        Err(e) => {
+            crate::OUTPUT.error(format!("Invalid value {} for {}", value, property).as_str());
+            Err(e)
+        }
+    }
+}
+
+/// Splits the arguments of `change` into `(device, property, value)` changes
+fn parse_batch<'a>(args: &[&'a str]) -> Result<Vec<(&'a str, &'a str, &'a str)>> {
+    if let [device, prop, value] = args {
+        if !prop.contains('=') {
+            return Ok(vec![(*device, *prop, *value)]);
+        }
+    }
+
+    let mut changes = vec![];
+    let mut device: Option<&str> = None;
+    let mut pending = false;
+    for arg in args {
+        match arg.split_once('=') {
+            Some((target, value)) => {
+                let (target_device, prop) = match target.rsplit_once('.') {
+                    Some((d, p)) => (Some(d), p),
+                    None => (device, target),
+                };
+                match target_device {
+                    Some(d) if !d.is_empty() && !prop.is_empty() => {
+                        changes.push((d, prop, value));
+                        pending = false;
+                    }
+                    _ => {
+                        crate::OUTPUT.error(
+                            format!("Invalid assignment {}, expected property=value", arg)
+                                .as_str(),
+                        );
+                        return Err(Error::Conversion(None));
+                    }
+                }
+            }
+            None => {
+                if pending {
+                    break;
+                }
+                device = Some(*arg);
+                pending = true;
+            }
+        }
+    }
+
+    if pending || changes.is_empty() {
+        crate::OUTPUT.error(
+            format!(
+                "Nothing to change for {}, expected property=value",
+                device.unwrap_or("")
+            )
+            .as_str(),
+        );
+        return Err(Error::Conversion(None));
+    }
+
+    Ok(changes)
+}
+
+#[cfg(all(test, feature = "mice"))]
+mod tests {
+    use std::rc::Rc;
+
+    use super::{change_all, parse_batch, parse_property};
+    use crate::steelseries_core::{
+        support::DevicePool, Color, DeviceDefinition, DeviceProperty, Error, HidDeviceInfo,
+        MemoryBackend, Packet, PropertyValue, TaskOptions,
+    };
+    use steelcli::devices::SenseiTenMouse;
+
+    #[test]
+    fn wheel_color_in_batch() {
+        assert_eq!(
+            parse_batch(&["rival", "lc=red", "wc=blue"]).unwrap(),
+            vec![("rival", "lc", "red"), ("rival", "wc", "blue")]
+        );
+        assert_eq!(parse_property("wc").unwrap(), DeviceProperty::WheelLedColor);
+        assert!(matches!(
+            parse_property("xc"),
+            Err(Error::InvalidCapability)
+        ));
+
+        // A mouse with a wheel can not be driven like the Sensei Ten, which has none
+        let rival = DeviceDefinition::from_toml(
+            &include_str!("devices/definitions/senseiten.toml")
+                .replace("\"senseiten\"", "\"rival\"")
+                .replace("0x1832", "0x1724")
+                .replace("[\"lc\"]", "[\"lc\", \"wc\"]"),
+        )
+        .unwrap();
+        let info = HidDeviceInfo::new(0x1038, 0x1832, "1-1:1.1").with_interface(1);
+        let backend = MemoryBackend::new().with_info(info);
+        let transport = backend.transport().clone();
+        let pool = DevicePool::with_definitions(
+            Rc::new(backend),
+            vec![rival, SenseiTenMouse::definition()],
+        )
+        .sync();
+        assert!(matches!(pool.lookup("rival"), Err(Error::NoDevice)));
+
+        let options = TaskOptions::default();
+        let unsupported = change_all(&pool, &["senseiten", "lc=red", "wc=blue"], &options);
+        assert!(matches!(unsupported, Err(Error::InvalidCapability)));
+        assert!(transport.sent().is_empty());
+
+        // The current colour, as read back before changing it
+        let green = PropertyValue::Color(Color::from((0x00, 0xff, 0x00)));
+        pool.lookup("senseiten")
+            .unwrap()
+            .change_property(DeviceProperty::LedColor, green, &options)
+            .unwrap();
+        let green = transport.sent().remove(0);
+        if let Packet::FeatureReport(report) = &green {
+            transport.push_reply(report);
+            transport.push_reply(report);
+        }
+
+        // Red goes through and blue fails, so green is restored
+        transport.fail_after(1);
+        let failed = change_all(&pool, &["senseiten", "lc=red", "lc=blue"], &options);
+        assert!(matches!(failed, Err(Error::UsbComm)));
+        let sent = transport.sent();
+        assert_eq!(sent.len(), 4);
+        assert_ne!(sent[1], green);
+        assert_eq!(sent[2..], [green.clone(), green]);
+    }
 }
@@ -0,0 +1,56 @@
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::report::{self, OutputFormat};
+use crate::steelseries_core::{user_palette, Color, Result, NAMED_COLORS};
+
+/// A colour name as printed by the machine readable output formats
+#[derive(Serialize)]
+struct ColorReport {
+    name: String,
+    value: String,
+    palette: bool,
+}
+
+/// Lists every colour name, those of the user palette first, with a swatch of the colour
+pub fn colors(format: OutputFormat) -> Result<()> {
+    let palette = user_palette();
+    let mut named: Vec<(&str, &Color)> = NAMED_COLORS
+        .iter()
+        .filter(|(name, _)| palette.get(name).is_none())
+        .map(|(name, color)| (*name, color))
+        .collect();
+    named.sort_by_key(|(name, _)| *name);
+
+    let entries = palette
+        .iter()
+        .map(|(name, color)| (name, color, true))
+        .chain(named.into_iter().map(|(name, color)| (name, color, false)));
+
+    if format != OutputFormat::Table {
+        let reports: Vec<ColorReport> = entries
+            .map(|(name, color, palette)| ColorReport {
+                name: name.to_string(),
+                value: color.to_string(),
+                palette,
+            })
+            .collect();
+        return report::print(format, &reports);
+    }
+
+    for (name, color, palette) in entries {
+        let swatch = "    ".on_truecolor(color.red(), color.green(), color.blue());
+        match palette {
+            true => println!(
+                "{} {} {}\t{}",
+                swatch,
+                format!("{:<24}", name).bold(),
+                color,
+                "palette".dimmed()
+            ),
+            false => println!("{} {:<24} {}", swatch, name, color),
+        }
+    }
+
+    Ok(())
+}
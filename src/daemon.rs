@@ -0,0 +1,678 @@
+//# A long running process holding devices open and accepting commands over a Unix socket.
+//#
+//# The protocol is JSON-RPC 2.0, one request per line and one response per line:
+//#
+//#     {"jsonrpc": "2.0", "id": 1, "method": "change", "params": {"device": "senseiten", "property": "lc", "value": "red"}}
+//#     {"jsonrpc": "2.0", "id": 1, "result": null}
+//#
+//# Methods are `list`, `get`, `change`, `apply`, `profile.apply`, `effect.start` and `effect.stop`.
+//# Effects keep playing between requests, until stopped or replaced by another change.
+//#
+//# The socket is only accessible to the user running the daemon. Requests are limited to
+//# `MAX_REQUEST_LENGTH` bytes, a client sending a longer one gets an error and is disconnected.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    os::unix::{
+        fs::{DirBuilderExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::steelseries_core::{
+    support::DevicePool, Animation, DeviceProperty, Error, FromCode, ProfileStore, Result,
+    SteelseriesDevice, TaskOptions, ToCode, Transaction, FRAME_INTERVAL,
+};
+
+const SOCKET_NAME: &str = "steelcli.sock";
+
+/// How long a response may wait for a client to read the previous ones
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The longest request line accepted, newline excluded
+const MAX_REQUEST_LENGTH: usize = 64 * 1024;
+
+/// The socket the daemon listens on when none is given: `$XDG_RUNTIME_DIR/steelcli.sock`.
+/// There is no shared fallback, without `$XDG_RUNTIME_DIR` the socket must be given.
+pub fn default_socket_path() -> Result<PathBuf> {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir).join(SOCKET_NAME)),
+        _ => {
+            crate::OUTPUT.error("XDG_RUNTIME_DIR is not set, give the daemon socket with --socket");
+            Err(Error::Generic("No daemon socket"))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct GetParams {
+    device: String,
+    property: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChangeParams {
+    device: String,
+    property: String,
+    value: String,
+    #[serde(default)]
+    save: bool,
+}
+
+#[derive(Deserialize)]
+struct ApplyParams {
+    changes: Vec<ChangeParams>,
+}
+
+#[derive(Deserialize)]
+struct EffectParams {
+    device: String,
+    effect: Option<String>,
+    color: Option<Colors>,
+    period: Option<String>,
+    property: Option<String>,
+}
+
+/// The colours of an effect, a single one or a list
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Colors {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize)]
+struct ProfileParams {
+    name: String,
+    #[serde(default)]
+    save: bool,
+}
+
+#[derive(Serialize)]
+struct DeviceEntry<'a> {
+    instance: String,
+    slug: &'a str,
+    name: &'a str,
+    path: Option<&'a str>,
+    serial: Option<&'a str>,
+}
+
+/// A failed request, turned into a JSON-RPC error object
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    const PARSE: i64 = -32700;
+    const INVALID_REQUEST: i64 = -32600;
+    const METHOD_NOT_FOUND: i64 = -32601;
+    const INVALID_PARAMS: i64 = -32602;
+    const DEVICE: i64 = -32000;
+
+    fn new(code: i64, message: String) -> Self {
+        Self { code, message }
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(e: Error) -> Self {
+        Self::new(Self::DEVICE, format!("{}", e))
+    }
+}
+
+/// A connection to the daemon, with the part of the next request received so far
+struct Client {
+    stream: UnixStream,
+    buf: Vec<u8>,
+}
+
+struct Daemon {
+    pool: DevicePool,
+    /// Options shared by every request, the `save` flag being given per request
+    options: TaskOptions,
+    /// Effects playing, by the device they play on as found by `effect_key`
+    effects: Vec<(String, Animation)>,
+}
+
+/// Listens on `socket` and serves requests until the process is killed.
+/// Requests of every connection are served in turn, as they arrive.
+pub fn daemon(pool: DevicePool, socket: &Path, options: TaskOptions) -> Result<()> {
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            crate::OUTPUT
+                .error(format!("A daemon is already listening on {}", socket.display()).as_str());
+            return Err(Error::Generic("Daemon already running"));
+        }
+        fs::remove_file(socket)?;
+    }
+
+    let listener = bind_private(socket)?;
+    crate::OUTPUT.log(format!("Listening on {}", socket.display()).as_str());
+
+    // Waiting for connections or requests never blocks, so that effects keep playing
+    listener.set_nonblocking(true)?;
+
+    let mut daemon = Daemon {
+        pool,
+        options,
+        effects: vec![],
+    };
+    let mut clients: Vec<Client> = vec![];
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => match Client::new(stream) {
+                Ok(client) => clients.push(client),
+                Err(e) => crate::OUTPUT.verbose(format!("Connection closed: {:?}", e)),
+            },
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => crate::OUTPUT.warn(format!("Cannot accept connection: {}", e).as_str()),
+        }
+
+        clients.retain_mut(|client| match daemon.serve(client) {
+            Ok(open) => open,
+            Err(e) => {
+                crate::OUTPUT.verbose(format!("Connection closed: {:?}", e));
+                false
+            }
+        });
+
+        daemon.tick();
+        thread::sleep(FRAME_INTERVAL);
+    }
+}
+
+/// Listens on `socket` without it ever being reachable by other users: the socket is bound
+/// in a fresh directory only the current user can enter, restricted to its owner, then moved
+/// to `socket`
+fn bind_private(socket: &Path) -> Result<UnixListener> {
+    let parent = match socket.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let dir = parent.join(format!(".steelcli-{}", std::process::id()));
+    fs::DirBuilder::new().mode(0o700).create(&dir)?;
+
+    let staged = dir.join(SOCKET_NAME);
+    let listener = UnixListener::bind(&staged).and_then(|listener| {
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&staged, socket)?;
+        Ok(listener)
+    });
+    if listener.is_err() {
+        let _ = fs::remove_file(&staged);
+    }
+    fs::remove_dir(&dir)?;
+
+    Ok(listener?)
+}
+
+/// Sends a single request to the daemon listening on `socket` and returns its result
+pub fn request(socket: &Path, method: &str, params: Value) -> Result<Value> {
+    let stream = match UnixStream::connect(socket) {
+        Ok(stream) => stream,
+        Err(e) => {
+            crate::OUTPUT
+                .error(format!("No daemon listening on {}: {}", socket.display(), e).as_str());
+            return Err(e.into());
+        }
+    };
+
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    writeln!(&stream, "{}", request)?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    let response: Value = serde_json::from_str(&line).map_err(|_| Error::Conversion(None))?;
+    match response.get("error") {
+        Some(error) => {
+            let message = error.get("message").and_then(|m| m.as_str()).unwrap_or("");
+            crate::OUTPUT.error(format!("The daemon failed to {}: {}", method, message).as_str());
+            Err(Error::Generic("Daemon request failed"))
+        }
+        None => Ok(response.get("result").cloned().unwrap_or(Value::Null)),
+    }
+}
+
+impl Client {
+    fn new(stream: UnixStream) -> Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        Ok(Self {
+            stream,
+            buf: vec![],
+        })
+    }
+
+    /// Reads what the client sent since the last call, returning whether it is still connected.
+    /// Reading stops once more than a whole request is waiting.
+    fn receive(&mut self) -> Result<bool> {
+        let mut chunk = [0x00; 4096];
+        while self.buf.len() <= MAX_REQUEST_LENGTH {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    // The last request may not end with a newline
+                    if !self.buf.is_empty() {
+                        self.buf.push(b'\n');
+                    }
+                    return Ok(false);
+                }
+                Ok(read) => self.buf.extend_from_slice(&chunk[..read]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(true),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// The next complete request line, if any
+    fn next_line(&mut self) -> Option<String> {
+        let end = self.buf.iter().position(|b| *b == b'\n')?;
+        let line: Vec<u8> = self.buf.drain(..=end).collect();
+        Some(String::from_utf8_lossy(&line).to_string())
+    }
+
+    fn send(&mut self, response: Value) -> Result<()> {
+        self.stream.set_nonblocking(false)?;
+        writeln!(self.stream, "{}", response)?;
+        self.stream.set_nonblocking(true)?;
+        Ok(())
+    }
+}
+
+impl Daemon {
+    /// Answers the requests `client` sent since the last call, returning whether it is
+    /// still connected
+    fn serve(&mut self, client: &mut Client) -> Result<bool> {
+        let open = client.receive()?;
+        while let Some(line) = client.next_line() {
+            if line.len() > MAX_REQUEST_LENGTH + 1 {
+                return too_long(client);
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => {
+                    crate::OUTPUT.verbose(format!("Request {}", request.method));
+                    let result = self.handle(&request.method, request.params);
+                    response(request.id, result)
+                }
+                Err(e) => response(
+                    Value::Null,
+                    Err(RpcError::new(RpcError::PARSE, e.to_string())),
+                ),
+            };
+            client.send(response)?;
+        }
+
+        if client.buf.len() > MAX_REQUEST_LENGTH {
+            return too_long(client);
+        }
+
+        Ok(open)
+    }
+
+    /// Renders the next frame of every effect, dropping the effects that are over or failed.
+    /// Effects of disconnected devices resume once the device is back.
+    fn tick(&mut self) {
+        let pool = &self.pool;
+        let options = self.options;
+        self.effects.retain_mut(|(key, animation)| {
+            let device = match pool.find(key) {
+                Some(device) => device,
+                None => return true,
+            };
+            if let Err(e) = animation.render(device, &options) {
+                crate::OUTPUT.warn(format!("Stopping effect on {}: {}", key, e).as_str());
+                return false;
+            }
+            !animation.is_finished()
+        });
+    }
+
+    /// Stops the effect playing on the device matching `spec`, if any
+    fn stop_effect(&mut self, spec: &str) {
+        if let Some(key) = self.pool.find(spec).map(effect_key) {
+            self.effects.retain(|(k, _)| *k != key);
+        }
+    }
+
+    fn handle(&mut self, method: &str, params: Value) -> std::result::Result<Value, RpcError> {
+        match method {
+            "list" => {
+                self.refresh()?;
+                let devices: Vec<DeviceEntry> = self
+                    .pool
+                    .instances()
+                    .into_iter()
+                    .map(|(instance, device)| DeviceEntry {
+                        instance,
+                        slug: device.get_slug(),
+                        name: device.get_name(),
+                        path: device.get_instance().map(|i| i.path.as_str()),
+                        serial: device
+                            .get_instance()
+                            .and_then(|i| i.serial_number.as_deref()),
+                    })
+                    .collect();
+                Ok(json!(devices))
+            }
+            "get" => {
+                let params: GetParams = parse_params(params)?;
+                let device = self.device(&params.device)?;
+                match params.property {
+                    Some(code) => Ok(json!(device.read_property(property(&code)?)?.to_string())),
+                    None => {
+                        let mut values = serde_json::Map::new();
+                        for c in device.enumerate_capabilities() {
+                            if let Ok(value) = device.read_property(*c) {
+                                values.insert(c.to_code().to_string(), json!(value.to_string()));
+                            }
+                        }
+                        Ok(Value::Object(values))
+                    }
+                }
+            }
+            "change" => {
+                let params: ChangeParams = parse_params(params)?;
+                self.change(&params)?;
+                Ok(Value::Null)
+            }
+            "effect.start" => {
+                let params: EffectParams = parse_params(params)?;
+                let name = params.effect.as_deref().unwrap_or("");
+                let colors: Vec<&str> = match &params.color {
+                    Some(Colors::One(color)) => vec![color.as_str()],
+                    Some(Colors::Many(colors)) => colors.iter().map(String::as_str).collect(),
+                    None => vec![],
+                };
+                let effect =
+                    crate::effect::parse_effect(name, &colors, params.period.as_deref())?;
+                let property = params.property.as_deref().map(property).transpose()?;
+
+                let device = self.device(&params.device)?;
+                let key = effect_key(device);
+                let animation = Animation::new(effect, device, property)?;
+                self.effects.retain(|(k, _)| *k != key);
+                self.effects.push((key, animation));
+                Ok(Value::Null)
+            }
+            "effect.stop" => {
+                let params: EffectParams = parse_params(params)?;
+                self.device(&params.device)?;
+                self.stop_effect(&params.device);
+                Ok(Value::Null)
+            }
+            "apply" => {
+                let params: ApplyParams = parse_params(params)?;
+                self.apply(&params)?;
+                Ok(Value::Null)
+            }
+            "profile.apply" => {
+                let params: ProfileParams = parse_params(params)?;
+                let profile = ProfileStore::user()?.load(&params.name)?;
+                let options = TaskOptions {
+                    save: params.save,
+                    ..self.options
+                };
+                self.refresh()?;
+                for (spec, _) in profile.iter() {
+                    self.stop_effect(spec);
+                }
+                crate::profiles::apply(&self.pool, &profile, &options)?;
+                Ok(Value::Null)
+            }
+            _ => Err(RpcError::new(
+                RpcError::METHOD_NOT_FOUND,
+                format!("Unknown method {}", method),
+            )),
+        }
+    }
+
+    fn change(&mut self, params: &ChangeParams) -> std::result::Result<(), RpcError> {
+        let options = TaskOptions {
+            save: params.save,
+            ..self.options
+        };
+        self.device(&params.device)?;
+        self.stop_effect(&params.device);
+        let device = self.device(&params.device)?;
+        let property = property(&params.property)?;
+        let mut transaction = Transaction::new();
+        transaction.add(device, property, property.value_kind().parse(&params.value)?);
+        transaction.apply(&options)?;
+        Ok(())
+    }
+
+    /// Applies every change or none of them, saving when any change asks for it
+    fn apply(&mut self, params: &ApplyParams) -> std::result::Result<(), RpcError> {
+        if params.changes.iter().any(|c| self.pool.find(&c.device).is_none()) {
+            self.refresh()?;
+        }
+        for change in params.changes.iter() {
+            self.stop_effect(&change.device);
+        }
+
+        let mut transaction = Transaction::new();
+        for change in params.changes.iter() {
+            let device = self.pool.lookup(&change.device)?;
+            let property = property(&change.property)?;
+            transaction.add(device, property, property.value_kind().parse(&change.value)?);
+        }
+
+        let options = TaskOptions {
+            save: params.changes.iter().any(|c| c.save),
+            ..self.options
+        };
+        transaction.apply(&options)?;
+        Ok(())
+    }
+
+    /// Finds a device, looking for newly connected devices if it is not known yet
+    fn device(&mut self, spec: &str) -> std::result::Result<&dyn SteelseriesDevice, RpcError> {
+        if self.pool.find(spec).is_none() {
+            self.refresh()?;
+        }
+
+        Ok(self.pool.lookup(spec)?)
+    }
+
+    fn refresh(&mut self) -> Result<()> {
+        for event in self.pool.refresh()? {
+            crate::OUTPUT.verbose(format!("{:?}", event));
+        }
+        Ok(())
+    }
+}
+
+/// Identifies the device an effect plays on across pool refreshes: its path when bound
+/// to a connected device, its slug otherwise
+fn effect_key(device: &dyn SteelseriesDevice) -> String {
+    match device.get_instance() {
+        Some(instance) => instance.path.clone(),
+        None => device.get_slug().to_string(),
+    }
+}
+
+fn property(code: &str) -> std::result::Result<DeviceProperty, RpcError> {
+    Ok(DeviceProperty::from_code(code)?)
+}
+
+fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> std::result::Result<T, RpcError> {
+    serde_json::from_value(params)
+        .map_err(|e| RpcError::new(RpcError::INVALID_PARAMS, e.to_string()))
+}
+
+/// Tells `client` its request is too long, the connection is then dropped
+fn too_long(client: &mut Client) -> Result<bool> {
+    let message = format!("Requests are limited to {} bytes", MAX_REQUEST_LENGTH);
+    let error = RpcError::new(RpcError::INVALID_REQUEST, message);
+    client.send(response(Value::Null, Err(error)))?;
+    Ok(false)
+}
+
+fn response(id: Value, result: std::result::Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": e.code, "message": e.message }
+        }),
+    }
+}
+
+#[cfg(all(test, feature = "mice"))]
+mod tests {
+    use std::{
+        fs,
+        io::{BufRead, BufReader, Write},
+        os::unix::{fs::PermissionsExt, net::UnixStream},
+        rc::Rc,
+    };
+
+    use serde_json::{json, Value};
+
+    use super::{bind_private, Client, Daemon, RpcError, MAX_REQUEST_LENGTH};
+    use steelcli::devices::SenseiTenMouse;
+    use crate::steelseries_core::{
+        support::DevicePool, Color, DeviceProperty, HidDeviceInfo, MemoryBackend, MemoryTransport,
+        Packet, PropertyValue, TaskOptions,
+    };
+
+    /// A daemon serving a single Sensei Ten, along with the transport it talks to
+    fn daemon() -> (Daemon, MemoryTransport) {
+        let info = HidDeviceInfo::new(0x1038, 0x1832, "1-1:1.1").with_interface(1);
+        let backend = MemoryBackend::new().with_info(info);
+        let transport = backend.transport().clone();
+        let daemon = Daemon {
+            pool: DevicePool::with_definitions(Rc::new(backend), vec![SenseiTenMouse::definition()])
+                .sync(),
+            options: TaskOptions::default(),
+            effects: vec![],
+        };
+        (daemon, transport)
+    }
+
+    fn logo_color(value: &str) -> Value {
+        json!({ "device": "senseiten", "property": "lc", "value": value })
+    }
+
+    /// Sets the logo to green without going through the daemon, then queues the report
+    /// sent as the colour read back by the next `reads` changes
+    fn set_green(daemon: &Daemon, transport: &MemoryTransport, reads: usize) -> Packet {
+        let green = PropertyValue::Color(Color::from((0x00, 0xff, 0x00)));
+        daemon
+            .pool
+            .lookup("senseiten")
+            .unwrap()
+            .change_property(DeviceProperty::LedColor, green, &TaskOptions::default())
+            .unwrap();
+        let green = transport.sent().pop().unwrap();
+        if let Packet::FeatureReport(report) = &green {
+            for _ in 0..reads {
+                transport.push_reply(report);
+            }
+        }
+        green
+    }
+
+    #[test]
+    fn change() {
+        let (mut daemon, transport) = daemon();
+        let green = set_green(&daemon, &transport, 1);
+        assert!(matches!(
+            daemon.handle("change", logo_color("red")),
+            Ok(Value::Null)
+        ));
+        assert!(matches!(
+            transport.sent().as_slice(),
+            [first, Packet::FeatureReport(report)] if *first == green && report.starts_with(&[0x5b, 0x00])
+        ));
+
+        let error = daemon.handle("change", logo_color("not a colour")).err();
+        assert_eq!(error.map(|e| e.code), Some(RpcError::DEVICE));
+
+        // Nothing is sent when the current colour can not be read back
+        let error = daemon.handle("change", logo_color("blue")).err();
+        assert_eq!(error.map(|e| e.code), Some(RpcError::DEVICE));
+        assert_eq!(transport.sent().len(), 2);
+    }
+
+    #[test]
+    fn apply_rolls_back() {
+        let (mut daemon, transport) = daemon();
+        let green = set_green(&daemon, &transport, 2);
+
+        // Red goes through, blue fails, then green is restored in place of both
+        transport.fail_after(1);
+        let changes = json!({ "changes": [logo_color("red"), logo_color("blue")] });
+        let error = daemon.handle("apply", changes).err();
+        assert_eq!(error.map(|e| e.code), Some(RpcError::DEVICE));
+        let sent = transport.sent();
+        assert_eq!(sent.len(), 4);
+        assert_eq!(sent[2..], [green.clone(), green]);
+    }
+
+    #[test]
+    fn request_errors() {
+        let (mut daemon, _) = daemon();
+        let error = daemon.handle("reboot", Value::Null).err();
+        assert_eq!(error.map(|e| e.code), Some(RpcError::METHOD_NOT_FOUND));
+
+        let params = json!({ "device": "senseiten", "property": "lc" });
+        let error = daemon.handle("change", params).err();
+        assert_eq!(error.map(|e| e.code), Some(RpcError::INVALID_PARAMS));
+
+        let error = daemon.handle("change", json!([1, 2])).err();
+        assert_eq!(error.map(|e| e.code), Some(RpcError::INVALID_PARAMS));
+    }
+
+    #[test]
+    fn socket_is_private() {
+        let socket = std::env::temp_dir().join(format!("steelcli-test-{}.sock", std::process::id()));
+        let listener = bind_private(&socket).unwrap();
+        let mode = fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(UnixStream::connect(&socket).is_ok());
+        assert!(listener.accept().is_ok());
+        fs::remove_file(&socket).unwrap();
+    }
+
+    #[test]
+    fn long_request_drops_client() {
+        let (mut daemon, _) = daemon();
+        let (stream, mut peer) = UnixStream::pair().unwrap();
+        let mut client = Client::new(stream).unwrap();
+        writeln!(peer, "{}", json!({ "jsonrpc": "2.0", "id": 1, "method": "list" })).unwrap();
+        assert!(daemon.serve(&mut client).unwrap());
+
+        peer.write_all(&vec![b' '; MAX_REQUEST_LENGTH + 1]).unwrap();
+        assert!(!daemon.serve(&mut client).unwrap());
+        let mut replies = BufReader::new(peer).lines();
+        let list: Value = serde_json::from_str(&replies.next().unwrap().unwrap()).unwrap();
+        assert!(list.get("result").is_some());
+        let error: Value = serde_json::from_str(&replies.next().unwrap().unwrap()).unwrap();
+        assert_eq!(error["error"]["code"], json!(RpcError::INVALID_REQUEST));
+    }
+}
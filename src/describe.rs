@@ -1,13 +1,18 @@
 use colored::Colorize;
 
+use crate::report::{self, DeviceReport, OutputFormat};
 use crate::steelseries_core::{Result, SteelseriesDevice, ToCode, ToDescription};
 use crate::utils;
 
-pub fn describe(device: &dyn SteelseriesDevice) -> Result<()> {
+pub fn describe(device: &dyn SteelseriesDevice, format: OutputFormat) -> Result<()> {
+    if format != OutputFormat::Table {
+        return report::print(format, &DeviceReport::new(device, None));
+    }
+
     print_device_info(device);
 
+    println!("  Attributes:");
     for c in device.enumerate_capabilities() {
-        println!("  Attributes:");
         println!("    {}\t{}", c.to_code().bold(), c.to_description());
     }
 
@@ -17,17 +22,22 @@ pub fn describe(device: &dyn SteelseriesDevice) -> Result<()> {
 pub(crate) fn print_device_info(handle: &dyn SteelseriesDevice) {
     println!("{}", handle.get_name().cyan().bold());
 
-    println!("  {}: {}", "Nick", handle.get_slug());
+    println!("  Nick: {}", handle.get_slug());
 
     println!(
-        "  {}: {}",
-        "Vendor ID",
+        "  Vendor ID: {}",
         utils::format_radix(handle.get_vendor_id() as u32, 16)
     );
 
     println!(
-        "  {}: {}",
-        "Device ID",
+        "  Device ID: {}",
         utils::format_radix(handle.get_product_id() as u32, 16)
     );
+
+    if let Some(instance) = handle.get_instance() {
+        println!("  Path: {}", instance.path);
+        if let Some(serial) = &instance.serial_number {
+            println!("  Serial: {}", serial);
+        }
+    }
 }
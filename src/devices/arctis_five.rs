@@ -1,149 +1,153 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, rc::Rc};
 
 use crate::{
     get_profile_value,
     steelseries_core::{
-        Color, DeviceProfileValue, DeviceProperty, Error, RGBGradient, RGBGradientSettings, Result,
-        Side, SteelseriesDevice, TaskOptions, ToDescription, STEELSERIES_VENDOR_ID,
+        Backend, Color, DeviceDefinition, DeviceProfileValue, DeviceProperty, Error,
+        HidDeviceInfo, PropertyValue, RGBGradient, Result, Side, SteelseriesDevice, TaskOptions,
+        ToDescription,
     },
 };
 
-const ARCTIS_5_PID: u16 = 0x12aa;
-
 pub struct ArctisFiveHeadphones {
-    vendor_id: u16,
-    product_id: u16,
+    definition: DeviceDefinition,
     capabilities: Vec<DeviceProperty>,
-    profile: HashMap<&'static str, DeviceProfileValue>,
+    backend: Rc<dyn Backend>,
+    instance: Option<HidDeviceInfo>,
 }
 
 impl ArctisFiveHeadphones {
-    pub fn new() -> Self {
-        let mut profile = HashMap::new();
-        profile.insert("rgbgradh_header_length", DeviceProfileValue::Hex(26));
-        profile.insert(
-            "rgbgradh_led_id_offsets",
-            DeviceProfileValue::ByteList(&[0]),
-        );
-        profile.insert("rgbgradh_duration_offset", DeviceProfileValue::Byte(1));
-        profile.insert("rgbgradh_duration_length", DeviceProfileValue::Byte(2));
-        profile.insert("rgbgradh_repeat_offset", DeviceProfileValue::Byte(17));
-        profile.insert("rgbgradh_triggers_offset", DeviceProfileValue::Byte(21));
-        profile.insert("rgbgradh_color_count_offset", DeviceProfileValue::Hex(25));
-
-        profile.insert(
-            "logo_color_command",
-            DeviceProfileValue::ByteList(&[0x5B, 0x00]),
-        );
-
-        profile.insert("save_command", DeviceProfileValue::ByteList(&[0x59, 0x00]));
+    /// The properties this driver can change
+    pub const PROPERTIES: &'static [DeviceProperty] = &[
+        DeviceProperty::LeftHeadphoneLedColor,
+        DeviceProperty::RightHeadphoneLedColor,
+        DeviceProperty::HeadphonesColor,
+    ];
+
+    pub fn new(backend: Rc<dyn Backend>) -> Self {
+        Self::from_definition(Self::definition(), backend, None)
+    }
+
+    /// The built-in definition of this device
+    pub fn definition() -> DeviceDefinition {
+        DeviceDefinition::from_toml(include_str!("definitions/arctis5.toml"))
+            .expect("Invalid built-in Arctis 5 definition")
+    }
 
+    /// Builds the device from `definition`. When `instance` is given, the device is
+    /// bound to that connected device instead of the first one matching its ids.
+    pub fn from_definition(
+        definition: DeviceDefinition,
+        backend: Rc<dyn Backend>,
+        instance: Option<HidDeviceInfo>,
+    ) -> Self {
         Self {
-            vendor_id: STEELSERIES_VENDOR_ID,
-            product_id: ARCTIS_5_PID,
-            capabilities: vec![
-                DeviceProperty::from("lhc"),
-                DeviceProperty::from("rhc"),
-                DeviceProperty::from("hc"),
-            ],
-            profile: profile,
+            capabilities: definition.properties(),
+            definition,
+            backend,
+            instance,
         }
     }
 
-    pub fn set_headphone_color(&self, _side: Side, color: Color) -> Result<()> {
-        if let Ok(handle) = self.open() {
-            let header_length = get_profile_value!(self, "rgbgradh_header_length", as_hex);
-            let led_id_offsets = get_profile_value!(self, "rgbgradh_led_id_offsets", as_byte_list);
-            let duration_offset = get_profile_value!(self, "rgbgradh_duration_offset", as_byte);
-            let duration_length = get_profile_value!(self, "rgbgradh_duration_length", as_byte);
-            let repeat_offset = get_profile_value!(self, "rgbgradh_repeat_offset", as_byte);
-            let triggers_offset = get_profile_value!(self, "rgbgradh_triggers_offset", as_byte);
-            let color_count_offset =
-                get_profile_value!(self, "rgbgradh_color_count_offset", as_hex);
-
-            let command = get_profile_value!(self, "logo_color_command", as_byte_list);
-            let rgbgradient = RGBGradient::from(color);
-
-            let processed = rgbgradient.process(RGBGradientSettings {
-                header_length: *header_length,
-                led_id_offsets: led_id_offsets,
-                duration_offset: *duration_offset,
-                duration_length: *duration_length,
-                repeat_offset: *repeat_offset,
-                triggers_offset: *triggers_offset,
-                color_count_offset: *color_count_offset,
-            });
-
-            let merged_command = [command, processed.as_slice()].concat();
-
-            handle.send_feature_report(merged_command.as_slice())
-        } else {
-            Err(Error::OpenDevice)
-        }
+    pub fn set_headphone_color(&self, _side: Side, color: Color, options: &TaskOptions) -> Result<()> {
+        let command = get_profile_value!(self, "logo_color_command", as_byte_list);
+        let ack = self.get_profile_value("logo_color_ack").and_then(|v| v.as_byte_list());
+        super::send_gradient(self, command, ack, &RGBGradient::from(color), options)
+    }
+
+    pub fn get_headphone_color(&self, _side: Side) -> Result<Color> {
+        let command = get_profile_value!(self, "logo_color_command", as_byte_list);
+        let gradient = super::read_gradient(self, command)?;
+        gradient.iter_colors().next().cloned().ok_or(Error::Conversion(None))
     }
 }
 
 impl SteelseriesDevice for ArctisFiveHeadphones {
-    fn enumerate_capabilities(&self) -> std::slice::Iter<DeviceProperty> {
+    fn enumerate_capabilities(&self) -> std::slice::Iter<'_, DeviceProperty> {
         self.capabilities.iter()
     }
 
     fn get_name(&self) -> &str {
-        "Arctis Five Headphones"
+        self.definition.name.as_str()
     }
 
     fn get_slug(&self) -> &str {
-        "arctis5"
+        self.definition.slug.as_str()
     }
 
     fn change_property(
         &self,
         property: DeviceProperty,
-        value: &str,
-        _options: &TaskOptions,
+        value: PropertyValue,
+        options: &TaskOptions,
     ) -> Result<()> {
         let capability = self.capabilities.iter().find(|c| **c == property);
         match capability {
             Some(prop) => {
-                super::OUTPUT
-                    .verbose(format!("Changing {} to {}", prop.to_description(), value).as_str());
+                log::debug!("Changing {} to {}", prop.to_description(), value);
+                let color = match value {
+                    PropertyValue::Color(color) => color,
+                    _ => return Err(Error::Conversion(None)),
+                };
                 match prop {
                     DeviceProperty::LeftHeadphoneLedColor => {
-                        self.set_headphone_color(Side::Left, Color::from(value))
+                        self.set_headphone_color(Side::Left, color, options)
                     }
                     DeviceProperty::RightHeadphoneLedColor => {
-                        self.set_headphone_color(Side::Right, Color::from(value))
+                        self.set_headphone_color(Side::Right, color, options)
                     }
                     DeviceProperty::HeadphonesColor => {
-                        match self.set_headphone_color(Side::Left, Color::from(value)) {
-                            Ok(_) => self.set_headphone_color(Side::Right, Color::from(value)),
+                        match self.set_headphone_color(Side::Left, color.clone(), options) {
+                            Ok(_) => self.set_headphone_color(Side::Right, color, options),
                             Err(e) => Err(e),
                         }
                     }
-                    _ => {
-                        super::OUTPUT.verbose(format!(
-                            "Property {} not supported by this device",
-                            prop.to_description()
-                        ));
-                        Ok(())
-                    }
+                    _ => Err(Error::Unsupported),
                 }
             }
             None => Err(Error::InvalidCapability),
         }
     }
 
+    fn read_property(&self, property: DeviceProperty) -> Result<PropertyValue> {
+        let capability = self.capabilities.iter().find(|c| **c == property);
+        match capability {
+            Some(DeviceProperty::LeftHeadphoneLedColor) | Some(DeviceProperty::HeadphonesColor) => {
+                Ok(PropertyValue::Color(self.get_headphone_color(Side::Left)?))
+            }
+            Some(DeviceProperty::RightHeadphoneLedColor) => {
+                Ok(PropertyValue::Color(self.get_headphone_color(Side::Right)?))
+            }
+            Some(_) => Err(Error::Unsupported),
+            None => Err(Error::InvalidCapability),
+        }
+    }
+
+    fn save(&self, options: &TaskOptions) -> Result<()> {
+        super::save(self, options)
+    }
+
     fn get_vendor_id(&self) -> u16 {
-        self.vendor_id
+        self.definition.vendor_id
     }
 
     fn get_product_id(&self) -> u16 {
-        self.product_id
+        self.definition.product_id
     }
 
-    fn get_profile(
-        &self,
-    ) -> Option<&std::collections::HashMap<&str, crate::steelseries_core::DeviceProfileValue>> {
-        Some(&self.profile)
+    fn get_profile(&self) -> Option<&HashMap<String, DeviceProfileValue>> {
+        Some(&self.definition.profile)
+    }
+
+    fn get_backend(&self) -> &dyn Backend {
+        self.backend.as_ref()
+    }
+
+    fn get_instance(&self) -> Option<&HidDeviceInfo> {
+        self.instance.as_ref()
+    }
+
+    fn accepts(&self, info: &HidDeviceInfo) -> bool {
+        self.definition.accepts(info)
     }
 }
@@ -1,16 +1,232 @@
+
+#[cfg(feature = "headsets")]
 mod arctis_five;
+#[cfg(feature = "mice")]
 mod sensei_ten;
 
-use super::OUTPUT;
+use std::rc::Rc;
+
+#[cfg(feature = "headsets")]
 pub use arctis_five::*;
+#[cfg(feature = "mice")]
 pub use sensei_ten::*;
 
+use crate::steelseries_core::{
+    Backend, DeviceDefinition, DeviceProperty, HidDeviceInfo, SteelseriesDevice,
+};
+#[cfg(any(feature = "headsets", feature = "mice"))]
+use crate::steelseries_core::{
+    calibration_matrix, ColorCorrection, Error, RGBGradient, RGBGradientSettings, Result,
+    SteelseriesDeviceHandle, TaskOptions, IDENTITY_MATRIX,
+};
+
 #[macro_export]
 macro_rules! get_profile_value {
     ( $target:ident, $k:literal ) => {
-        $target.get_profile_value($k).unwrap()
+        $target
+            .get_profile_value($k)
+            .ok_or($crate::steelseries_core::Error::MissingProfileValue($k))?
     };
     ( $target:ident, $k:literal, $t:tt ) => {
-        $target.get_profile_value($k).unwrap().$t().unwrap()
+        $target
+            .get_profile_value($k)
+            .and_then(|v| v.$t())
+            .ok_or($crate::steelseries_core::Error::MissingProfileValue($k))?
+    };
+}
+
+/// Definitions of the devices supported out of the box
+pub fn builtin_definitions() -> Vec<DeviceDefinition> {
+    vec![
+        #[cfg(feature = "headsets")]
+        ArctisFiveHeadphones::definition(),
+        #[cfg(feature = "mice")]
+        SenseiTenMouse::definition(),
+    ]
+}
+
+/// Builds the device described by `definition`, using the driver it names,
+/// optionally bound to the connected device `instance`.
+/// Returns `None` if no such driver exists.
+pub fn from_definition(
+    definition: DeviceDefinition,
+    backend: Rc<dyn Backend>,
+    instance: Option<HidDeviceInfo>,
+) -> Option<Box<dyn SteelseriesDevice>> {
+    #[cfg(not(any(feature = "headsets", feature = "mice")))]
+    let _ = (backend, instance);
+
+    match definition.driver.as_str() {
+        #[cfg(feature = "headsets")]
+        "arctis_five" => Some(Box::new(ArctisFiveHeadphones::from_definition(
+            definition, backend, instance,
+        ))),
+        #[cfg(feature = "mice")]
+        "sensei_ten" => Some(Box::new(SenseiTenMouse::from_definition(
+            definition, backend, instance,
+        ))),
+        _ => None,
+    }
+}
+
+/// The properties the driver named `driver` can change, or `None` if no such driver exists
+pub fn driver_properties(driver: &str) -> Option<&'static [DeviceProperty]> {
+    match driver {
+        #[cfg(feature = "headsets")]
+        "arctis_five" => Some(ArctisFiveHeadphones::PROPERTIES),
+        #[cfg(feature = "mice")]
+        "sensei_ten" => Some(SenseiTenMouse::PROPERTIES),
+        _ => None,
+    }
+}
+
+/// Reads the gradient layout from the `rgbgradh_*` values of a device profile.
+/// Durations are only limited by their length unless the profile sets a minimum or maximum.
+#[cfg(any(feature = "headsets", feature = "mice"))]
+fn gradient_settings(device: &dyn SteelseriesDevice) -> Result<RGBGradientSettings<'_>> {
+    let duration_limit = |key: &str, default: u16| {
+        device
+            .get_profile_value(key)
+            .and_then(|v| v.as_hex())
+            .unwrap_or(default)
     };
+
+    Ok(RGBGradientSettings {
+        header_length: get_profile_value!(device, "rgbgradh_header_length", as_hex),
+        led_id_offsets: get_profile_value!(device, "rgbgradh_led_id_offsets", as_byte_list),
+        duration_offset: get_profile_value!(device, "rgbgradh_duration_offset", as_byte),
+        duration_length: get_profile_value!(device, "rgbgradh_duration_length", as_byte),
+        duration_min: duration_limit("rgbgradh_duration_min", 0),
+        duration_max: duration_limit("rgbgradh_duration_max", u16::MAX),
+        repeat_offset: get_profile_value!(device, "rgbgradh_repeat_offset", as_byte),
+        triggers_offset: get_profile_value!(device, "rgbgradh_triggers_offset", as_byte),
+        triggers_mask: device
+            .get_profile_value("rgbgradh_triggers_mask")
+            .and_then(|v| v.as_byte())
+            .unwrap_or(0x00),
+        color_count_offset: get_profile_value!(device, "rgbgradh_color_count_offset", as_hex),
+        correction: color_correction(device)?,
+    })
+}
+
+/// Reads the colour correction from the `color_*` values of a device profile: the
+/// `color_brightness` percentage, the `color_gamma` exponent, either one for all channels
+/// or one per channel, and the `color_balance` of white from 0 to 255 per channel.
+/// The calibration of the device, if any, comes on top. Colours are sent as they are
+/// otherwise.
+#[cfg(any(feature = "headsets", feature = "mice"))]
+fn color_correction(device: &dyn SteelseriesDevice) -> Result<ColorCorrection> {
+    let invalid =
+        |key: &str| Error::Definition(format!("Invalid {} in {}", key, device.get_slug()));
+    let mut correction = ColorCorrection {
+        matrix: calibration_matrix(device).unwrap_or(IDENTITY_MATRIX),
+        ..Default::default()
+    };
+
+    if let Some(value) = device.get_profile_value("color_brightness") {
+        correction.brightness = value
+            .as_float()
+            .filter(|b| (0.0..=100.0).contains(b))
+            .ok_or_else(|| invalid("color_brightness"))?
+            / 100.0;
+    }
+
+    if let Some(value) = device.get_profile_value("color_gamma") {
+        let gamma = match value.as_float() {
+            Some(gamma) => vec![gamma; 3],
+            None => value.as_float_list().unwrap_or_default(),
+        };
+        correction.gamma = match gamma.as_slice() {
+            [r, g, b] if gamma.iter().all(|g| *g > 0.0) => [*r, *g, *b],
+            _ => return Err(invalid("color_gamma")),
+        };
+    }
+
+    if let Some(value) = device.get_profile_value("color_balance") {
+        correction.balance = match value.as_float_list().as_deref() {
+            Some([r, g, b]) if [r, g, b].iter().all(|c| (0.0..=255.0).contains(*c)) => {
+                [r / 255.0, g / 255.0, b / 255.0]
+            }
+            _ => return Err(invalid("color_balance")),
+        };
+    }
+
+    Ok(correction)
+}
+
+/// Reads back the gradient currently set through `command`, by asking the device
+/// for the feature report with the same report id
+#[cfg(any(feature = "headsets", feature = "mice"))]
+fn read_gradient(device: &dyn SteelseriesDevice, command: &[u8]) -> Result<RGBGradient> {
+    let settings = gradient_settings(device)?;
+    let mut buf = vec![0x00; command.len() + settings.header_length as usize + 3 + 4 * 14];
+    buf[0] = *command.first().ok_or(Error::Conversion(None))?;
+
+    let handle = device.open()?;
+    let read = handle.get_feature_report(buf.as_mut_slice())?;
+    let payload = buf
+        .get(command.len()..read)
+        .ok_or(Error::UsbComm)?;
+
+    RGBGradient::decode(payload, settings)
+}
+
+/// Sends `gradient` to the device as a feature report prefixed by `command`, with its
+/// colours corrected, then saves it to the device memory if requested.
+/// When `ack` is given, the device must answer the command with an input report
+/// starting with it; the same goes for saving and the `save_ack` profile value.
+#[cfg(any(feature = "headsets", feature = "mice"))]
+fn send_gradient(
+    device: &dyn SteelseriesDevice,
+    command: &[u8],
+    ack: Option<&[u8]>,
+    gradient: &RGBGradient,
+    options: &TaskOptions,
+) -> Result<()> {
+    let mut settings = gradient_settings(device)?;
+    if let Some(brightness) = options.brightness {
+        settings.correction.brightness = brightness.min(100) as f64 / 100.0;
+    }
+    let processed = gradient.process(settings)?;
+    let merged_command = [command, processed.as_slice()].concat();
+
+    if !options.dry {
+        let handle = device.open()?;
+        handle.send_feature_report(merged_command.as_slice())?;
+        if let Some(ack) = ack {
+            handle.wait_ack(ack, options.timeout)?;
+        }
+        if options.save {
+            write_save(device, &handle, options)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Saves the current settings of the device to its memory
+#[cfg(any(feature = "headsets", feature = "mice"))]
+fn save(device: &dyn SteelseriesDevice, options: &TaskOptions) -> Result<()> {
+    if options.dry {
+        return Ok(());
+    }
+
+    write_save(device, &device.open()?, options)
+}
+
+/// Writes the `save_command` of the device to `handle`, then waits for `save_ack`
+/// when the device declares one
+#[cfg(any(feature = "headsets", feature = "mice"))]
+fn write_save(
+    device: &dyn SteelseriesDevice,
+    handle: &SteelseriesDeviceHandle,
+    options: &TaskOptions,
+) -> Result<()> {
+    let save_command = get_profile_value!(device, "save_command", as_byte_list);
+    handle.write(save_command)?;
+    if let Some(ack) = device.get_profile_value("save_ack").and_then(|v| v.as_byte_list()) {
+        handle.wait_ack(ack, options.timeout)?;
+    }
+
+    Ok(())
 }
@@ -1,142 +1,202 @@
-use std::{collections::HashMap, convert::TryFrom};
+use std::{collections::HashMap, rc::Rc};
 
 use crate::{
+    get_profile_value,
     steelseries_core::{
-        DeviceProfileValue, DeviceProperty, RGBGradient, RGBGradientSettings,
-        SteelseriesDevice, ToDescription, STEELSERIES_VENDOR_ID, TaskOptions,
+        Backend, DeviceDefinition, DeviceProfileValue, DeviceProperty, HidDeviceInfo,
+        PropertyValue, RGBGradient, SteelseriesDevice, TaskOptions, ToDescription,
     },
-    steelseries_core::{Error, Result}, get_profile_value,
+    steelseries_core::{Error, Result},
 };
 
-const SENSEI_TEN_PID: u16 = 0x1832;
-
 pub struct SenseiTenMouse {
-    vendor_id: u16,
-    product_id: u16,
+    definition: DeviceDefinition,
     capabilities: Vec<DeviceProperty>,
-    profile: HashMap<&'static str, DeviceProfileValue>,
+    backend: Rc<dyn Backend>,
+    instance: Option<HidDeviceInfo>,
 }
 
 impl SenseiTenMouse {
-    pub fn new() -> Self {
-        let mut profile = HashMap::new();
-        profile.insert("rgbgradh_header_length", DeviceProfileValue::Hex(26));
-        profile.insert(
-            "rgbgradh_led_id_offsets",
-            DeviceProfileValue::ByteList(&[0]),
-        );
-        profile.insert("rgbgradh_duration_offset", DeviceProfileValue::Byte(1));
-        profile.insert("rgbgradh_duration_length", DeviceProfileValue::Byte(2));
-        profile.insert("rgbgradh_repeat_offset", DeviceProfileValue::Byte(17));
-        profile.insert("rgbgradh_triggers_offset", DeviceProfileValue::Byte(21));
-        profile.insert("rgbgradh_color_count_offset", DeviceProfileValue::Hex(25));
-
-        profile.insert(
-            "logo_color_command",
-            DeviceProfileValue::ByteList(&[0x5b, 0x00]),
-        );
+    /// The properties this driver can change
+    pub const PROPERTIES: &'static [DeviceProperty] = &[DeviceProperty::LedColor];
+
+    pub fn new(backend: Rc<dyn Backend>) -> Self {
+        Self::from_definition(Self::definition(), backend, None)
+    }
 
-        profile.insert("save_command", DeviceProfileValue::ByteList(&[0x59, 0x00]));
+    /// The built-in definition of this device
+    pub fn definition() -> DeviceDefinition {
+        DeviceDefinition::from_toml(include_str!("definitions/senseiten.toml"))
+            .expect("Invalid built-in Sensei Ten definition")
+    }
 
+    /// Builds the device from `definition`. When `instance` is given, the device is
+    /// bound to that connected device instead of the first one matching its ids.
+    pub fn from_definition(
+        definition: DeviceDefinition,
+        backend: Rc<dyn Backend>,
+        instance: Option<HidDeviceInfo>,
+    ) -> Self {
         Self {
-            vendor_id: STEELSERIES_VENDOR_ID,
-            product_id: SENSEI_TEN_PID,
-            capabilities: vec![DeviceProperty::LedColor],
-            profile,
+            capabilities: definition.properties(),
+            definition,
+            backend,
+            instance,
         }
     }
 
     pub fn set_logo_color(&self, value: RGBGradient, options: &TaskOptions) -> Result<()> {
-        if let Ok(handle) = self.open() {
-            let header_length = get_profile_value!(self, "rgbgradh_header_length", as_hex);
-            let led_id_offsets = get_profile_value!(self, "rgbgradh_led_id_offsets", as_byte_list);
-            let duration_offset = get_profile_value!(self, "rgbgradh_duration_offset", as_byte);
-            let duration_length = get_profile_value!(self, "rgbgradh_duration_length", as_byte);
-            let repeat_offset = get_profile_value!(self, "rgbgradh_repeat_offset", as_byte);
-            let triggers_offset = get_profile_value!(self, "rgbgradh_triggers_offset", as_byte);
-            let color_count_offset =
-                get_profile_value!(self, "rgbgradh_color_count_offset", as_hex);
-
-            let command = get_profile_value!(self, "logo_color_command", as_byte_list);
-            let save_command = get_profile_value!(self, "save_command", as_byte_list);
-            let rgbgradient = RGBGradient::from(value);
-
-            let processed = rgbgradient.process(RGBGradientSettings {
-                header_length: *header_length,
-                led_id_offsets: led_id_offsets,
-                duration_offset: *duration_offset,
-                duration_length: *duration_length,
-                repeat_offset: *repeat_offset,
-                triggers_offset: *triggers_offset,
-                color_count_offset: *color_count_offset,
-            });
-
-            let merged_command = [command, processed.as_slice()].concat();
-
-            if !options.dry {
-                handle.send_feature_report(merged_command.as_slice())?;
-                if options.save {
-                    handle.write(save_command)?;
-                }
-            }
+        let command = get_profile_value!(self, "logo_color_command", as_byte_list);
+        let ack = self.get_profile_value("logo_color_ack").and_then(|v| v.as_byte_list());
+        super::send_gradient(self, command, ack, &value, options)
+    }
 
-            Ok(())
-        } else {
-            Err(Error::OpenDevice)
-        }
+    pub fn get_logo_color(&self) -> Result<RGBGradient> {
+        let command = get_profile_value!(self, "logo_color_command", as_byte_list);
+        super::read_gradient(self, command)
     }
 }
 
 impl SteelseriesDevice for SenseiTenMouse {
-    fn enumerate_capabilities(&self) -> std::slice::Iter<DeviceProperty> {
+    fn enumerate_capabilities(&self) -> std::slice::Iter<'_, DeviceProperty> {
         self.capabilities.iter()
     }
 
     fn get_name(&self) -> &str {
-        "Sensei Ten Mouse"
+        self.definition.name.as_str()
     }
 
     fn get_slug(&self) -> &str {
-        "senseiten"
+        self.definition.slug.as_str()
     }
 
     fn change_property(
         &self,
         property: DeviceProperty,
-        value: &str,
+        value: PropertyValue,
         options: &TaskOptions
     ) -> Result<()> {
         match self.supports_capability(property) {
             Some(prop) => {
-                super::OUTPUT
-                    .verbose(format!("Changing {} to {}", prop.to_description(), value).as_str());
-                match prop {
-                    DeviceProperty::LedColor => self.set_logo_color(
-                        RGBGradient::try_from(value).expect("Error parsing rgb gradient"),
-                        options
-                    ),
-                    _ => {
-                        super::OUTPUT.verbose(format!(
-                            "Property {} not supported by this device",
-                            prop.to_description()
-                        ));
-                        Ok(())
+                log::debug!("Changing {} to {}", prop.to_description(), value);
+                match (prop, value) {
+                    (DeviceProperty::LedColor, PropertyValue::Gradient(gradient)) => {
+                        self.set_logo_color(gradient, options)
                     }
+                    (DeviceProperty::LedColor, PropertyValue::Color(color)) => {
+                        self.set_logo_color(RGBGradient::from(color), options)
+                    }
+                    (DeviceProperty::LedColor, _) => Err(Error::Conversion(None)),
+                    _ => Err(Error::Unsupported),
                 }
             }
             None => Err(Error::InvalidCapability),
         }
     }
 
+    fn read_property(&self, property: DeviceProperty) -> Result<PropertyValue> {
+        match self.supports_capability(property) {
+            Some(DeviceProperty::LedColor) => Ok(PropertyValue::Gradient(self.get_logo_color()?)),
+            Some(_) => Err(Error::Unsupported),
+            None => Err(Error::InvalidCapability),
+        }
+    }
+
+    fn save(&self, options: &TaskOptions) -> Result<()> {
+        super::save(self, options)
+    }
+
     fn get_vendor_id(&self) -> u16 {
-        self.vendor_id
+        self.definition.vendor_id
     }
 
     fn get_product_id(&self) -> u16 {
-        self.product_id
+        self.definition.product_id
+    }
+
+    fn get_profile(&self) -> Option<&HashMap<String, DeviceProfileValue>> {
+        Some(&self.definition.profile)
+    }
+
+    fn get_backend(&self) -> &dyn Backend {
+        self.backend.as_ref()
+    }
+
+    fn get_instance(&self) -> Option<&HidDeviceInfo> {
+        self.instance.as_ref()
+    }
+
+    fn accepts(&self, info: &HidDeviceInfo) -> bool {
+        self.definition.accepts(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{rc::Rc, time::Duration};
+
+    use super::SenseiTenMouse;
+    use crate::steelseries_core::{
+        Color, DeviceProfileValue, DeviceProperty, Error, HidDeviceInfo, MemoryBackend, Packet,
+        PropertyValue, SteelseriesDevice, TaskOptions,
+    };
+
+    #[test]
+    fn logo_color_over_memory_transport() {
+        let backend = MemoryBackend::new()
+            .with_info(HidDeviceInfo::new(0x1038, 0x1832, "memory:0").with_interface(0))
+            .with_info(HidDeviceInfo::new(0x1038, 0x1832, "memory:1").with_interface(1));
+        let transport = backend.transport().clone();
+        let mouse = SenseiTenMouse::new(Rc::new(backend));
+        let options = TaskOptions {
+            save: true,
+            ..Default::default()
+        };
+
+        mouse
+            .change_property(
+                DeviceProperty::LedColor,
+                PropertyValue::Color(Color::from((0xff, 0x00, 0x00))),
+                &options,
+            )
+            .unwrap();
+
+        let mut report = vec![0x5b, 0x00];
+        report.append(&mut vec![0x00; 25]);
+        report.push(0x01);
+        report.extend_from_slice(&[0xff, 0x00, 0x00, 0xff, 0x00, 0x00, 0x00]);
+        assert_eq!(
+            transport.sent(),
+            vec![
+                Packet::FeatureReport(report),
+                Packet::Write(vec![0x59, 0x00])
+            ]
+        );
     }
 
-    fn get_profile(&self) -> Option<&HashMap<&str, crate::steelseries_core::DeviceProfileValue>> {
-        Some(&self.profile)
+    #[test]
+    fn logo_color_waits_for_ack() {
+        let backend = MemoryBackend::new().with_device(0x1038, 0x1832);
+        let transport = backend.transport().clone();
+        let mut definition = SenseiTenMouse::definition();
+        definition.interface = None;
+        definition
+            .profile
+            .insert("logo_color_ack".to_string(), DeviceProfileValue::ByteList(vec![0x5b]));
+        let mouse = SenseiTenMouse::from_definition(definition, Rc::new(backend), None);
+        let options = TaskOptions {
+            timeout: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let red = PropertyValue::Color(Color::from((0xff, 0x00, 0x00)));
+
+        let result = mouse.change_property(DeviceProperty::LedColor, red.clone(), &options);
+        assert!(matches!(result, Err(Error::Timeout)));
+
+        transport.push_reply(&[0x00, 0x01]);
+        transport.push_reply(&[0x5b, 0x01]);
+        mouse
+            .change_property(DeviceProperty::LedColor, red, &options)
+            .unwrap();
     }
 }
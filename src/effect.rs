@@ -0,0 +1,101 @@
+use std::{path::Path, time::Duration};
+
+use serde_json::json;
+
+use crate::steelseries_core::{
+    Animation, DeviceProperty, Effect, Error, PropertyKind, PropertyValue, Result,
+    SteelseriesDevice, TaskOptions,
+};
+use crate::utils;
+
+/// How long one cycle of an effect lasts when no period is given
+pub const DEFAULT_PERIOD: Duration = Duration::from_secs(2);
+
+/// Builds effect `name` from its colours and its period
+pub fn parse_effect(name: &str, colors: &[&str], period: Option<&str>) -> Result<Effect> {
+    let colors = colors
+        .iter()
+        .map(|raw| match PropertyKind::Color.parse(raw) {
+            Ok(PropertyValue::Color(color)) => Ok(color),
+            Ok(_) => Err(Error::Conversion(None)),
+            Err(e) => {
+                crate::OUTPUT.error(format!("Invalid colour {}", raw).as_str());
+                Err(e)
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let period = match period {
+        Some(raw) => match utils::parse_duration(raw) {
+            Some(period) => period,
+            None => {
+                crate::OUTPUT.error(format!("Invalid period {}", raw).as_str());
+                return Err(Error::Conversion(None));
+            }
+        },
+        None => DEFAULT_PERIOD,
+    };
+
+    Effect::new(name, colors, period).map_err(|e| {
+        crate::OUTPUT.error(format!("Cannot play {}: {}", name, e).as_str());
+        e
+    })
+}
+
+/// Plays `effect` on `device` in the foreground, until it is over, `until` elapsed
+/// or the process is interrupted
+pub fn play(
+    device: &dyn SteelseriesDevice,
+    effect: Effect,
+    property: Option<DeviceProperty>,
+    until: Option<Duration>,
+    options: &TaskOptions,
+) -> Result<()> {
+    let mut animation = match Animation::new(effect, device, property) {
+        Ok(animation) => animation,
+        Err(e) => {
+            crate::OUTPUT.error(format!("{} has no such colour property", device.get_name()).as_str());
+            return Err(e);
+        }
+    };
+
+    crate::OUTPUT.verbose(format!(
+        "Playing {} on {}",
+        animation.effect().name(),
+        device.get_slug()
+    ));
+    animation.play(device, until, options)
+}
+
+/// Asks the daemon listening on `socket` to play effect `name` on `device`, or to stop
+/// the effect playing on it when `name` is `stop`
+pub fn remote(
+    socket: &Path,
+    device: &str,
+    name: &str,
+    colors: &[&str],
+    period: Option<&str>,
+    property: Option<&str>,
+) -> Result<()> {
+    if name == "stop" {
+        crate::daemon::request(socket, "effect.stop", json!({ "device": device }))?;
+        return Ok(());
+    }
+
+    // Arguments are checked here so that mistakes are reported by the command itself
+    parse_effect(name, colors, period)?;
+    crate::daemon::request(
+        socket,
+        "effect.start",
+        json!({
+            "device": device,
+            "effect": name,
+            "color": colors,
+            "period": period,
+            "property": property,
+        }),
+    )?;
+    crate::OUTPUT.success(format!("Playing {} on {}", name, device).as_str());
+
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use colored::Colorize;
+
+use crate::steelseries_core::{Result, SteelseriesDevice, ToCode, ToDescription};
+
+/// Reads `prop` back from `device` and prints it. With no property, every
+/// readable capability of the device is printed.
+pub fn get(device: &dyn SteelseriesDevice, prop: Option<&str>) -> Result<()> {
+    match prop {
+        Some(prop) => {
+            let property = crate::change::parse_property(prop)?;
+            let value = device.read_property(property)?;
+            println!("{}", value);
+            Ok(())
+        }
+        None => {
+            println!("{}", device.get_name().cyan().bold());
+            for c in device.enumerate_capabilities() {
+                match device.read_property(*c) {
+                    Ok(value) => {
+                        println!("  {}\t{}\t{}", c.to_code().bold(), c.to_description(), value)
+                    }
+                    Err(e) => crate::OUTPUT.verbose(format!(
+                        "Cannot read {} back: {}",
+                        c.to_description(),
+                        e
+                    )),
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,34 @@
+//! Discovery and configuration of SteelSeries devices.
+//!
+//! Supported devices are gathered in a `DevicePool`, which can be narrowed down to the
+//! ones actually connected to the host. Properties are changed with typed values:
+//!
+//! ```no_run
+//! # #[cfg(feature = "hidapi-backend")]
+//! # fn main() -> Result<(), steelcli::steelseries_core::Error> {
+//! use steelcli::steelseries_core::{support::DevicePool, DeviceProperty, TaskOptions};
+//!
+//! let pool = DevicePool::new().sync();
+//! if let Some(mouse) = pool.find("senseiten") {
+//!     let value = DeviceProperty::LedColor.value_kind().parse("red")?;
+//!     let options = TaskOptions::default();
+//!     mouse.change_property(DeviceProperty::LedColor, value, &options)?;
+//! }
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "hidapi-backend"))]
+//! # fn main() {}
+//! ```
+//!
+//! The library reports what it does through the `log` crate.
+//!
+//! Cargo features select what gets built: `cli` for the `steelcli` binary, `headsets` and
+//! `mice` for device families, `hidapi-libusb` or `hidapi-hidraw` for the hidapi backend and
+//! `capture` for recording and replaying traffic. All of them are enabled by default.
+
+#[macro_use]
+extern crate lazy_static;
+
+pub mod devices;
+pub mod steelseries_core;
+pub mod utils;
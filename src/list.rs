@@ -1,18 +1,38 @@
 use crate::describe::print_device_info;
+use crate::report::{self, DeviceReport, OutputFormat};
 use crate::steelseries_core::support::DevicePool;
 use crate::steelseries_core::Result;
 
-pub fn list(pool: &DevicePool) -> Result<()> {
-    for device in pool.devices.iter() {
-        print_device_info(&**device);
+pub fn list(pool: &DevicePool, format: OutputFormat) -> Result<()> {
+    if format != OutputFormat::Table {
+        let reports: Vec<DeviceReport> = pool
+            .instances()
+            .into_iter()
+            .map(|(name, device)| DeviceReport::new(device, Some(name)))
+            .collect();
+        return report::print(format, &reports);
+    }
+
+    for (name, device) in pool.instances() {
+        print_device_info(device);
+        println!("  Instance: {}", name);
     }
 
     Ok(())
 }
 
-pub fn list_all() -> Result<()> {
-    for device in DevicePool::new().devices {
-        print_device_info(&*device);
+/// Lists the devices `pool` supports, connected or not. The pool must not be synced.
+pub fn list_all(pool: &DevicePool, format: OutputFormat) -> Result<()> {
+    if format != OutputFormat::Table {
+        let reports: Vec<DeviceReport> = pool
+            .devices()
+            .map(|device| DeviceReport::new(device, None))
+            .collect();
+        return report::print(format, &reports);
+    }
+
+    for device in pool.devices() {
+        print_device_info(device);
     }
 
     Ok(())
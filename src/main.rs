@@ -1,37 +1,55 @@
+#[cfg(not(feature = "hidapi-backend"))]
+compile_error!("the steelcli binary needs a hidapi backend, enable the hidapi-libusb or hidapi-hidraw feature");
+
 #[macro_use]
 extern crate clap;
 
 #[macro_use]
 extern crate lazy_static;
 
+mod calibrate;
 mod change;
+mod colors;
+mod daemon;
 mod describe;
-mod devices;
+mod effect;
+mod get;
 mod list;
-mod steelseries_core;
-mod utils;
+mod output;
+mod profiles;
+mod report;
+mod udev;
+mod watch;
 // mod runner;
 
-use change::change;
+use std::rc::Rc;
+
+use change::change_all;
 use clap::{App, ArgMatches};
 use list::{list, list_all};
-use steelseries_core::{support::DevicePool, LogLevel, SteelseriesDevice, Error, Result, TaskOptions};
+use output::LogLevel;
+use report::OutputFormat;
+use steelcli::{steelseries_core, utils};
+use steelseries_core::{
+    support::DevicePool, Backend, Calibration, Error, HidApiBackend, Palette, PersistentBackend,
+    ProfileStore, RecordingBackend, ReplayBackend, Result, SteelseriesDevice, TaskOptions,
+};
 
 lazy_static! {
-    pub static ref OUTPUT: crate::steelseries_core::Log = crate::steelseries_core::Log::new();
+    pub static ref OUTPUT: crate::output::Log = crate::output::Log::new();
 }
 
-lazy_static! {
-    pub static ref HIDAPI: hidapi::HidApi = hidapi::HidApi::new().unwrap();
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
 }
 
-fn main() -> Result<()> {
+fn run() -> Result<()> {
     let yaml = load_yaml!("config/cli.yml");
     let mut cli = App::from_yaml(yaml);
 
-    // Preallocate a synched device pool to use later
-    let device_pool = DevicePool::new().sync();
-
     // Clone "cli" to reuse it later
     let matches = cli.clone().get_matches();
 
@@ -52,62 +70,248 @@ fn main() -> Result<()> {
         OUTPUT.set_level(LogLevel::Verbose);
     }
 
+    let timeout = match matches.value_of("timeout") {
+        Some(raw) => match utils::parse_duration(raw) {
+            Some(timeout) => timeout,
+            None => {
+                OUTPUT.error(format!("Invalid timeout {}", raw).as_str());
+                return Err(Error::Conversion(None));
+            }
+        },
+        None => steelseries_core::DEFAULT_TIMEOUT,
+    };
+
+    let brightness = match matches.value_of("brightness") {
+        Some(raw) => match raw.trim_end_matches('%').parse::<u8>() {
+            Ok(brightness) if brightness <= 100 => Some(brightness),
+            _ => {
+                OUTPUT.error(format!("Invalid brightness {}, expected 0 to 100", raw).as_str());
+                return Err(Error::Conversion(None));
+            }
+        },
+        None => None,
+    };
+
+    let format: OutputFormat = matches.value_of("output").unwrap_or("table").parse()?;
+    OUTPUT.set_stderr(format != OutputFormat::Table);
+
+    // Messages from the library go through the same output
+    if log::set_logger(&*OUTPUT).is_ok() {
+        log::set_max_level(log::LevelFilter::Trace);
+    }
+
+    // Colour names of the user palettes are accepted wherever a colour is
+    let mut palette = Palette::user();
+    if let Some(path) = matches.value_of("palette") {
+        palette.extend(Palette::load(path)?);
+    }
+    steelseries_core::set_user_palette(palette);
+
+    // Every colour sent goes through the calibration of its device, colours are sent as they
+    // are when the calibration file is invalid
+    if let Ok(path) = Calibration::user_path() {
+        match Calibration::load(&path) {
+            Ok(calibration) => steelseries_core::set_calibration(calibration),
+            Err(e) => {
+                OUTPUT.warn(format!("Ignoring calibration {}: {}", path.display(), e).as_str())
+            }
+        }
+    }
+
+    if matches.subcommand_matches("colors").is_some() {
+        return colors::colors(format);
+    }
+
+    if let Some(cmd) = matches.subcommand_matches("daemon") {
+        let backend = Rc::new(PersistentBackend::new(backend_from_args(&matches)?));
+        let socket = socket_from_args(cmd)?;
+        return daemon::daemon(
+            DevicePool::with_backend(backend).sync(),
+            &socket,
+            TaskOptions {
+                dry,
+                timeout,
+                brightness,
+                ..Default::default()
+            },
+        );
+    }
+
+    // Effects played by the daemon need no device here
+    if let Some(cmd) = matches
+        .subcommand_matches("effect")
+        .filter(|cmd| cmd.occurrences_of("daemon") != 0)
+    {
+        let socket = socket_from_args(cmd)?;
+        return effect::remote(
+            &socket,
+            cmd.value_of("device").unwrap_or(""),
+            cmd.value_of("EFFECT").unwrap_or(""),
+            &effect_colors(cmd),
+            cmd.value_of("period"),
+            cmd.value_of("property"),
+        );
+    }
+
+    // Devices are opened once, even when a batch of changes goes through them
+    let backend = Rc::new(PersistentBackend::new(backend_from_args(&matches)?));
+    let device_pool = DevicePool::with_backend(backend);
+
+    if let Some(cmd) = matches.subcommand_matches("udev-rules") {
+        let install = match cmd.occurrences_of("install") {
+            0 => None,
+            _ => Some(cmd.value_of("install").unwrap_or(udev::DEFAULT_RULES_PATH)),
+        };
+        return udev::udev_rules(&device_pool, cmd.value_of("group"), install);
+    }
+
+    if matches.subcommand_matches("supported").is_some() {
+        return list_all(&device_pool, format);
+    }
+
+    // Preallocate a synched device pool to use later
+    let device_pool = device_pool.sync();
+
+    if let Some(cmd) = matches.subcommand_matches("watch") {
+        let interval = cmd.value_of("interval").unwrap_or("1s");
+        return match utils::parse_duration(interval) {
+            Some(interval) => watch::watch(device_pool, interval),
+            None => {
+                OUTPUT.error(format!("Invalid interval {}", interval).as_str());
+                Err(Error::Conversion(None))
+            }
+        };
+    }
+
     if let Some(_cmd) = matches.subcommand_matches("list") {
-        list(&device_pool)
-    } else if let Some(_cmd) = matches.subcommand_matches("supported") {
-        list_all()
+        list(&device_pool, format)
     } else if let Some(cmd) = matches.subcommand_matches("describe") {
-        if let Some(device) = device_from_args(&device_pool, cmd) {
-            describe::describe(device)
-        } else {
-            Err(Error::NoDevice)
+        describe::describe(device_from_args(&device_pool, cmd)?, format)
+    } else if let Some(cmd) = matches.subcommand_matches("get") {
+        get::get(device_from_args(&device_pool, cmd)?, cmd.value_of("PROPERTY"))
+    } else if let Some(cmd) = matches.subcommand_matches("profile") {
+        let store = ProfileStore::user()?;
+        let name = |cmd: &ArgMatches| cmd.value_of("NAME").unwrap_or("").to_string();
+        match cmd.subcommand() {
+            ("save", Some(cmd)) => {
+                let assignments: Vec<&str> =
+                    cmd.values_of("ASSIGNMENT").unwrap_or_default().collect();
+                profiles::save(&device_pool, &store, &name(cmd), &assignments)
+            }
+            ("apply", Some(cmd)) => {
+                let options = TaskOptions {
+                    dry,
+                    save: cmd.occurrences_of("save") != 0,
+                    timeout,
+                    brightness,
+                };
+                profiles::apply(&device_pool, &store.load(&name(cmd))?, &options)
+            }
+            ("list", Some(_)) => profiles::list(&store),
+            ("show", Some(cmd)) => profiles::show(&store, &name(cmd)),
+            ("delete", Some(cmd)) => profiles::delete(&store, &name(cmd)),
+            _ => {
+                println!("{}", cmd.usage());
+                Ok(())
+            }
         }
-    } else if let Some(cmd) = matches.subcommand_matches("change") {
-        let device = device_from_args(&device_pool, cmd);
+    } else if let Some(cmd) = matches.subcommand_matches("effect") {
+        let name = cmd.value_of("EFFECT").unwrap_or("");
+        if name == "stop" {
+            OUTPUT.error("Only effects playing in the daemon can be stopped, use --daemon");
+            return Err(Error::Unsupported);
+        }
+
+        let effect = effect::parse_effect(name, &effect_colors(cmd), cmd.value_of("period"))?;
+        let property = cmd.value_of("property").map(change::parse_property).transpose()?;
+        let until = match cmd.value_of("duration") {
+            Some(raw) => match utils::parse_duration(raw) {
+                Some(until) => Some(until),
+                None => {
+                    OUTPUT.error(format!("Invalid duration {}", raw).as_str());
+                    return Err(Error::Conversion(None));
+                }
+            },
+            None => None,
+        };
         let options = TaskOptions {
             dry,
-            save: matches.occurrences_of("save") != 0
+            timeout,
+            brightness,
+            ..Default::default()
         };
 
-        match device {
-            Some(device) => {
-                let prop = cmd.value_of("PROPERTY").unwrap_or("");
-                let value = cmd.value_of("VALUE").unwrap_or("");
-                change(device, prop, value, &options)
-            }
-            None => {
-                OUTPUT.error("No device specified");
-                Err(Error::NoDevice)
-            }
+        let device = device_from_args(&device_pool, cmd)?;
+        effect::play(device, effect, property, until, &options)
+    } else if let Some(cmd) = matches.subcommand_matches("calibrate") {
+        let device = device_from_args(&device_pool, cmd)?;
+        if cmd.occurrences_of("reset") != 0 {
+            return calibrate::reset(device);
         }
+
+        let property = cmd.value_of("property").map(change::parse_property).transpose()?;
+        let options = TaskOptions {
+            dry,
+            timeout,
+            brightness,
+            ..Default::default()
+        };
+        calibrate::calibrate(device, property, &options)
+    } else if let Some(cmd) = matches.subcommand_matches("change") {
+        let options = TaskOptions {
+            dry,
+            save: cmd.occurrences_of("save") != 0,
+            timeout,
+            brightness,
+        };
+
+        let args: Vec<&str> = cmd
+            .value_of("device")
+            .into_iter()
+            .chain(cmd.values_of("ASSIGNMENTS").into_iter().flatten())
+            .collect();
+        change_all(&device_pool, &args, &options)
     } else {
         cli.print_help().unwrap();
         Ok(())
     }
 }
 
+fn backend_from_args(matches: &ArgMatches) -> Result<Rc<dyn Backend>> {
+    if let Some(capture) = matches.value_of("replay") {
+        OUTPUT.verbose(format!("Replaying capture {}", capture));
+        Ok(Rc::new(ReplayBackend::new(capture)?))
+    } else if let Some(capture) = matches.value_of("record") {
+        OUTPUT.verbose(format!("Recording to capture {}", capture));
+        Ok(Rc::new(RecordingBackend::new(
+            Box::new(HidApiBackend::new()),
+            capture,
+        )?))
+    } else {
+        Ok(Rc::new(HidApiBackend::new()))
+    }
+}
+
 fn device_from_args<'a>(
     device_pool: &'a DevicePool,
     cmd: &'a ArgMatches,
-) -> Option<&'a dyn SteelseriesDevice> {
-    let device_spec_arg = cmd.value_of("device");
-    match device_spec_arg {
-        Some(device_spec) => {
-            if device_spec.contains(":") {
-                let parsed = utils::parse_device_id(cmd.value_of("device").unwrap_or("0:0"));
-                if let Some(device) = device_pool.find_one(parsed.0, parsed.1) {
-                    Some(device)
-                } else {
-                    None
-                }
-            } else {
-                if let Some(device) = device_pool.find_by_slug(device_spec) {
-                    Some(device)
-                } else {
-                    None
-                }
-            }
-        }
-        None => None,
+) -> Result<&'a dyn SteelseriesDevice> {
+    match cmd.value_of("device") {
+        Some(device_spec) => device_pool.lookup(device_spec),
+        None => Err(Error::NoDevice),
     }
 }
+
+fn socket_from_args(cmd: &ArgMatches) -> Result<std::path::PathBuf> {
+    match cmd.value_of("socket") {
+        Some(socket) => Ok(std::path::PathBuf::from(socket)),
+        None => daemon::default_socket_path(),
+    }
+}
+
+/// The colours given to `effect`, each one whole, as in `--color red "rgb(0, 0, 255)"`
+fn effect_colors<'a>(cmd: &'a ArgMatches) -> Vec<&'a str> {
+    cmd.values_of("color")
+        .map(|colors| colors.collect())
+        .unwrap_or_default()
+}
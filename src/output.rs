@@ -0,0 +1,103 @@
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+use colored::*;
+
+#[derive(PartialEq, PartialOrd)]
+pub enum LogLevel {
+    Verbose = 2,
+    Normal = 1,
+    Muted = 0
+}
+pub struct Log {
+    level: Mutex<LogLevel>,
+    to_stderr: AtomicBool,
+}
+
+const LOG_SIGN: &str = "🔵️"; // Blue circle
+const VERBOSE_SIGN: &str = "⚪️"; // White circle
+const SUCCESS_SIGN: &str = "✔️"; // Check mark
+const ERROR_SIGN: &str = "❌️"; // Cross mark
+const WARN_SIGN: &str = "⚠️"; // Warning sign
+
+impl Log {
+    pub fn new() -> Self {
+        Self {
+            level: Mutex::new(LogLevel::Normal),
+            to_stderr: AtomicBool::new(false),
+        }
+    }
+
+    /// Sends messages to stderr instead of stdout, leaving stdout to machine readable output
+    pub fn set_stderr(&self, to_stderr: bool) {
+        self.to_stderr.store(to_stderr, Ordering::Relaxed);
+    }
+
+    fn emit(&self, sign: &str, msg: &dyn Display) {
+        if self.to_stderr.load(Ordering::Relaxed) {
+            eprintln!("{} {}", sign, msg);
+        } else {
+            println!("{} {}", sign, msg);
+        }
+    }
+
+    pub fn set_level(&self, level: LogLevel) {
+        let mut w = self.level.lock().unwrap();
+        *w = level;
+    }
+
+    pub fn log(&self, msg: &str) {
+        if *self.level.lock().unwrap() >= LogLevel::Normal {
+            self.emit(LOG_SIGN, &msg);
+        }
+    }
+
+    pub fn verbose<S: Display>(&self, msg: S) {
+        if *self.level.lock().unwrap() >= LogLevel::Verbose {
+            self.emit(VERBOSE_SIGN, &msg);
+        }
+    }
+
+    pub fn success(&self, msg: &str) {
+        self.emit(SUCCESS_SIGN, &msg.green());
+    }
+
+    pub fn warn(&self, msg: &str) {
+        self.emit(WARN_SIGN, &msg.yellow());
+    }
+
+    pub fn error(&self, msg: &str) {
+        self.emit(ERROR_SIGN, &msg.red());
+    }
+}
+
+impl log::Log for Log {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        let level = self.level.lock().unwrap();
+        match metadata.level() {
+            log::Level::Error | log::Level::Warn => true,
+            log::Level::Info => *level >= LogLevel::Normal,
+            log::Level::Debug | log::Level::Trace => *level >= LogLevel::Verbose,
+        }
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let msg = record.args().to_string();
+        match record.level() {
+            log::Level::Error => self.error(&msg),
+            log::Level::Warn => self.warn(&msg),
+            log::Level::Info => self.log(&msg),
+            log::Level::Debug | log::Level::Trace => self.verbose(msg),
+        }
+    }
+
+    fn flush(&self) {}
+}
@@ -0,0 +1,170 @@
+use colored::Colorize;
+
+use crate::steelseries_core::{
+    support::DevicePool, Error, Profile, ProfileStore, Result, TaskOptions, ToCode, Transaction,
+};
+
+/// Saves profile `name`. Assignments are given as `device.property=value`; with no
+/// assignments, the current values of every connected device are saved instead.
+pub fn save(
+    pool: &DevicePool,
+    store: &ProfileStore,
+    name: &str,
+    assignments: &[&str],
+) -> Result<()> {
+    let profile = if assignments.is_empty() {
+        current(pool)
+    } else {
+        let mut profile = Profile::new();
+        for assignment in assignments {
+            let (device, code, value) = parse_assignment(assignment)?;
+            let property = crate::change::parse_property(code)?;
+            if let Err(e) = property.value_kind().parse(value) {
+                crate::OUTPUT.error(format!("Invalid value {} for {}", value, property).as_str());
+                return Err(e);
+            }
+            profile.set(device, code, value);
+        }
+        profile
+    };
+
+    if profile.is_empty() {
+        crate::OUTPUT.error("Nothing to save");
+        return Err(Error::NoDevice);
+    }
+
+    store.save(name, &profile)?;
+    crate::OUTPUT.success(format!("Saved profile {}", name).as_str());
+    Ok(())
+}
+
+/// Applies every value of `profile` to the devices in `pool`, all together or not at
+/// all. Devices that are not connected are skipped.
+pub fn apply(pool: &DevicePool, profile: &Profile, options: &TaskOptions) -> Result<()> {
+    let mut transaction = Transaction::new();
+    for (spec, values) in profile.iter() {
+        let device = match pool.find(spec) {
+            Some(device) => device,
+            None => {
+                crate::OUTPUT.warn(format!("Skipping {}: device not connected", spec).as_str());
+                continue;
+            }
+        };
+
+        for (code, value) in values {
+            let property = crate::change::parse_property(code)?;
+            transaction.add(device, property, crate::change::parse_value(property, value)?);
+        }
+    }
+
+    crate::change::apply(&transaction, options)
+}
+
+pub fn list(store: &ProfileStore) -> Result<()> {
+    for name in store.list()? {
+        println!("{}", name);
+    }
+
+    Ok(())
+}
+
+pub fn show(store: &ProfileStore, name: &str) -> Result<()> {
+    let profile = store.load(name)?;
+    for (device, values) in profile.iter() {
+        println!("{}", device.cyan().bold());
+        for (code, value) in values {
+            println!("  {}\t{}", code.bold(), value);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn delete(store: &ProfileStore, name: &str) -> Result<()> {
+    store.delete(name)?;
+    crate::OUTPUT.success(format!("Deleted profile {}", name).as_str());
+    Ok(())
+}
+
+/// The readable values of every connected device. Devices are named by slug, or by
+/// `slug#N` when more than one device of the same kind is connected.
+fn current(pool: &DevicePool) -> Profile {
+    let instances = pool.instances();
+    let mut profile = Profile::new();
+    for (instance, device) in instances.iter() {
+        let shared = instances
+            .iter()
+            .filter(|(_, d)| d.get_slug() == device.get_slug())
+            .count()
+            > 1;
+        let name = if shared {
+            instance.as_str()
+        } else {
+            device.get_slug()
+        };
+
+        for c in device.enumerate_capabilities() {
+            match device.read_property(*c) {
+                Ok(value) => profile.set(name, c.to_code(), value.to_string().as_str()),
+                Err(e) => crate::OUTPUT.verbose(format!(
+                    "Cannot read {} of {}: {}",
+                    c.to_code(),
+                    name,
+                    e
+                )),
+            }
+        }
+    }
+
+    profile
+}
+
+/// Splits `device.property=value`
+fn parse_assignment(assignment: &str) -> Result<(&str, &str, &str)> {
+    let parsed = assignment
+        .split_once('=')
+        .and_then(|(target, value)| target.rsplit_once('.').map(|(d, p)| (d, p, value)));
+    match parsed {
+        Some((device, code, value)) if !device.is_empty() && !code.is_empty() => {
+            Ok((device, code, value))
+        }
+        _ => {
+            crate::OUTPUT.error(
+                format!(
+                    "Invalid assignment {}, expected device.property=value",
+                    assignment
+                )
+                .as_str(),
+            );
+            Err(Error::Conversion(None))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_assignment;
+
+    #[test]
+    fn assignments() {
+        assert_eq!(
+            parse_assignment("senseiten.lc=red").ok(),
+            Some(("senseiten", "lc", "red"))
+        );
+        assert_eq!(
+            parse_assignment("1038:1832.lc=rgb(1, 2, 3)").ok(),
+            Some(("1038:1832", "lc", "rgb(1, 2, 3)"))
+        );
+        assert_eq!(
+            parse_assignment("senseiten#2.lc=").ok(),
+            Some(("senseiten#2", "lc", ""))
+        );
+        for invalid in ["senseiten.lc", "lc=red", ".lc=red", "senseiten.=red"] {
+            assert!(
+                parse_assignment(invalid).is_err(),
+                "{} was accepted",
+                invalid
+            );
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::steelseries_core::{Error, Result, SteelseriesDevice, ToCode, ToDescription};
+use crate::utils;
+
+/// How commands print what they found
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Yaml,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "yaml" => Ok(OutputFormat::Yaml),
+            _ => Err(Error::Conversion(None)),
+        }
+    }
+}
+
+/// A device as printed by the machine readable output formats
+#[derive(Serialize)]
+pub struct DeviceReport {
+    name: String,
+    slug: String,
+    vendor_id: String,
+    product_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instance: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    serial: Option<String>,
+    capabilities: Vec<CapabilityReport>,
+}
+
+#[derive(Serialize)]
+struct CapabilityReport {
+    code: String,
+    description: String,
+}
+
+impl DeviceReport {
+    /// Describes `device`, known on the command line as `instance` when connected
+    pub fn new(device: &dyn SteelseriesDevice, instance: Option<String>) -> Self {
+        Self {
+            name: device.get_name().to_string(),
+            slug: device.get_slug().to_string(),
+            vendor_id: utils::format_radix(device.get_vendor_id() as u32, 16),
+            product_id: utils::format_radix(device.get_product_id() as u32, 16),
+            instance,
+            path: device.get_instance().map(|i| i.path.clone()),
+            serial: device.get_instance().and_then(|i| i.serial_number.clone()),
+            capabilities: device
+                .enumerate_capabilities()
+                .map(|c| CapabilityReport {
+                    code: c.to_code().to_string(),
+                    description: c.to_description().to_string(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Prints `value` as JSON or YAML. Tables are printed by each command.
+pub fn print<T: Serialize>(format: OutputFormat, value: &T) -> Result<()> {
+    let output = match format {
+        OutputFormat::Json => serde_json::to_string_pretty(value).map_err(|_| Error::Conversion(None))?,
+        OutputFormat::Yaml => serde_yaml::to_string(value).map_err(|_| Error::Conversion(None))?,
+        OutputFormat::Table => return Err(Error::Unsupported),
+    };
+    println!("{}", output.trim_end());
+
+    Ok(())
+}
@@ -0,0 +1,203 @@
+#[cfg(feature = "hidapi-backend")]
+use std::{ffi::CString, sync::Mutex};
+
+use super::{Error, MemoryTransport, Result, Transport};
+
+/// Describes a HID device as reported by a `Backend`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HidDeviceInfo {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub path: String,
+    pub serial_number: Option<String>,
+    pub product: Option<String>,
+
+    /// The USB interface number, or -1 when the platform does not report it
+    pub interface_number: i32,
+
+    /// The usage page and usage of the top level collection, 0 when not reported
+    pub usage_page: u16,
+    pub usage: u16,
+}
+
+impl HidDeviceInfo {
+    pub fn new(vendor_id: u16, product_id: u16, path: &str) -> Self {
+        Self {
+            vendor_id,
+            product_id,
+            path: path.to_string(),
+            serial_number: None,
+            product: None,
+            interface_number: -1,
+            usage_page: 0,
+            usage: 0,
+        }
+    }
+
+    pub fn with_interface(mut self, interface_number: i32) -> Self {
+        self.interface_number = interface_number;
+        self
+    }
+
+    pub fn with_usage(mut self, usage_page: u16, usage: u16) -> Self {
+        self.usage_page = usage_page;
+        self.usage = usage;
+        self
+    }
+
+    /// Checks if this device mathches a given `vendor_id:product_id` combination
+    pub fn matches(&self, vendor_id: u16, product_id: u16) -> bool {
+        self.vendor_id == vendor_id && self.product_id == product_id
+    }
+}
+
+#[cfg(feature = "hidapi-backend")]
+impl From<&hidapi::DeviceInfo> for HidDeviceInfo {
+    fn from(info: &hidapi::DeviceInfo) -> Self {
+        Self {
+            vendor_id: info.vendor_id(),
+            product_id: info.product_id(),
+            path: info.path().to_string_lossy().into_owned(),
+            // hidapi reports an empty serial number for devices without one
+            serial_number: info
+                .serial_number()
+                .filter(|s| !s.is_empty())
+                .map(String::from),
+            product: info.product_string().map(String::from),
+            interface_number: info.interface_number(),
+            usage_page: info.usage_page(),
+            usage: info.usage(),
+        }
+    }
+}
+
+/// A source of HID devices. Backends enumerate what is connected to the host
+/// and open transports to talk to it.
+pub trait Backend {
+    /// Lists the devices currently connected
+    fn device_list(&self) -> Vec<HidDeviceInfo>;
+
+    /// Opens a transport to the device described by `info`
+    fn open(&self, info: &HidDeviceInfo) -> Result<Box<dyn Transport>>;
+
+    /// Updates the list of connected devices, for backends caching it
+    fn refresh(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "hidapi-backend")]
+lazy_static! {
+    /// hidapi allows a single `HidApi` per process, shared by every `HidApiBackend`.
+    /// It is created on first use.
+    static ref HIDAPI: Mutex<Option<hidapi::HidApi>> = Mutex::new(None);
+}
+
+/// The default backend, talking to real devices through hidapi
+#[cfg(feature = "hidapi-backend")]
+pub struct HidApiBackend {
+    api: &'static Mutex<Option<hidapi::HidApi>>,
+}
+
+#[cfg(feature = "hidapi-backend")]
+impl HidApiBackend {
+    pub fn new() -> Self {
+        Self { api: &HIDAPI }
+    }
+
+    /// Runs `f` over the process wide `HidApi`, creating it if needed
+    fn with_api<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut hidapi::HidApi) -> Result<T>,
+    {
+        let mut api = self
+            .api
+            .lock()
+            .map_err(|_| Error::Generic("HID API lock poisoned"))?;
+        if api.is_none() {
+            *api = Some(hidapi::HidApi::new()?);
+        }
+
+        match api.as_mut() {
+            Some(api) => f(api),
+            None => Err(Error::OpenDevice),
+        }
+    }
+}
+
+#[cfg(feature = "hidapi-backend")]
+impl Default for HidApiBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "hidapi-backend")]
+impl Backend for HidApiBackend {
+    fn device_list(&self) -> Vec<HidDeviceInfo> {
+        let devices = self.with_api(|api| Ok(api.device_list().map(HidDeviceInfo::from).collect()));
+        match devices {
+            Ok(devices) => devices,
+            Err(e) => {
+                log::warn!("Cannot list HID devices: {:?}", e);
+                vec![]
+            }
+        }
+    }
+
+    fn open(&self, info: &HidDeviceInfo) -> Result<Box<dyn Transport>> {
+        let path = CString::new(info.path.as_str()).map_err(|_| Error::OpenDevice)?;
+        self.with_api(|api| match api.open_path(&path) {
+            Ok(device) => Ok(Box::new(device) as Box<dyn Transport>),
+            Err(_) => Err(Error::UsbComm),
+        })
+    }
+
+    fn refresh(&self) -> Result<()> {
+        self.with_api(|api| Ok(api.refresh_devices()?))
+    }
+}
+
+/// A backend exposing a fixed set of fake devices, all sharing the same `MemoryTransport`
+#[derive(Default)]
+pub struct MemoryBackend {
+    devices: Vec<HidDeviceInfo>,
+    transport: MemoryTransport,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a fake connected device
+    pub fn with_device(self, vendor_id: u16, product_id: u16) -> Self {
+        let path = format!("memory:{}", self.devices.len());
+        self.with_info(HidDeviceInfo::new(vendor_id, product_id, path.as_str()))
+    }
+
+    /// Adds a fake connected device described by `info`
+    pub fn with_info(mut self, info: HidDeviceInfo) -> Self {
+        self.devices.push(info);
+        self
+    }
+
+    /// The transport every opened device writes to
+    pub fn transport(&self) -> &MemoryTransport {
+        &self.transport
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn device_list(&self) -> Vec<HidDeviceInfo> {
+        self.devices.clone()
+    }
+
+    fn open(&self, info: &HidDeviceInfo) -> Result<Box<dyn Transport>> {
+        if self.devices.contains(info) {
+            Ok(Box::new(self.transport.clone()))
+        } else {
+            Err(Error::OpenDevice)
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//# Colour calibration of individual devices, so that the same colour looks alike on all of
+//# them, as stored in `~/.config/steelcli/calibration.toml`
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{ColorMatrix, Error, Result, SteelseriesDevice};
+
+lazy_static! {
+    static ref USER_CALIBRATION: RwLock<Calibration> = RwLock::new(Calibration::new());
+}
+
+/// Calibration matrices by device, as stored in a calibration file:
+///
+/// ```toml
+/// [senseiten]
+/// matrix = [[1.0, 0.0, 0.0], [0.0, 0.82, 0.0], [0.0, 0.0, 0.7]]
+/// ```
+///
+/// Tables are named after the device slug and serial number, as in `senseiten@0123`, or
+/// after the slug alone for devices reporting no serial number. A table named after the
+/// slug alone applies to every device of the model without a calibration of its own.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Calibration {
+    devices: BTreeMap<String, DeviceCalibration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct DeviceCalibration {
+    matrix: ColorMatrix,
+}
+
+impl Calibration {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The name `device` is calibrated under, its slug followed by its serial number if known
+    pub fn key(device: &dyn SteelseriesDevice) -> String {
+        match device.get_instance().and_then(|i| i.serial_number.as_deref()) {
+            Some(serial) => format!("{}@{}", device.get_slug(), serial),
+            None => device.get_slug().to_string(),
+        }
+    }
+
+    /// The calibration of `device`, or else of its model
+    pub fn get(&self, device: &dyn SteelseriesDevice) -> Option<ColorMatrix> {
+        self.devices
+            .get(Self::key(device).as_str())
+            .or_else(|| self.devices.get(device.get_slug()))
+            .map(|calibration| calibration.matrix)
+    }
+
+    /// Calibrates `device` with `matrix`, replacing its previous calibration
+    pub fn set(&mut self, device: &dyn SteelseriesDevice, matrix: ColorMatrix) {
+        self.devices
+            .insert(Self::key(device), DeviceCalibration { matrix });
+    }
+
+    /// Forgets the calibration of `device`, returning whether it had one
+    pub fn remove(&mut self, device: &dyn SteelseriesDevice) -> bool {
+        self.devices.remove(Self::key(device).as_str()).is_some()
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self> {
+        toml::from_str(source).map_err(|e| Error::Calibration(e.to_string()))
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).map_err(|e| Error::Calibration(e.to_string()))
+    }
+
+    /// Loads a calibration file, an empty calibration if it does not exist
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(source) => Self::from_toml(source.as_str()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_toml()?)?;
+        Ok(())
+    }
+
+    /// The calibration file in the user configuration directory,
+    /// eg. `~/.config/steelcli/calibration.toml`
+    pub fn user_path() -> Result<PathBuf> {
+        crate::utils::config_dir()
+            .map(|dir| dir.join("calibration.toml"))
+            .ok_or(Error::Generic(
+                "Cannot find the user configuration directory",
+            ))
+    }
+}
+
+/// Makes `calibration` apply to every colour later sent to the devices it calibrates
+pub fn set_calibration(calibration: Calibration) {
+    if let Ok(mut user) = USER_CALIBRATION.write() {
+        *user = calibration;
+    }
+}
+
+/// The calibration matrix of `device` set with `set_calibration`, if any
+#[cfg(any(feature = "headsets", feature = "mice"))]
+pub(crate) fn calibration_matrix(device: &dyn SteelseriesDevice) -> Option<ColorMatrix> {
+    USER_CALIBRATION
+        .read()
+        .ok()
+        .and_then(|calibration| calibration.get(device))
+}
+
+#[cfg(all(test, feature = "mice"))]
+mod tests {
+    use std::rc::Rc;
+
+    use super::Calibration;
+    use crate::devices::SenseiTenMouse;
+    use crate::steelseries_core::{HidDeviceInfo, MemoryBackend, IDENTITY_MATRIX};
+
+    /// A Sensei Ten reporting `serial`, if any
+    fn mouse(serial: Option<&str>) -> SenseiTenMouse {
+        let mut info = HidDeviceInfo::new(0x1038, 0x1832, "memory:0");
+        info.serial_number = serial.map(String::from);
+        SenseiTenMouse::from_definition(
+            SenseiTenMouse::definition(),
+            Rc::new(MemoryBackend::new().with_info(info.clone())),
+            Some(info),
+        )
+    }
+
+    #[test]
+    fn calibration_by_serial_or_model() {
+        let first = mouse(Some("0123"));
+        let second = mouse(Some("4567"));
+        let anonymous = mouse(None);
+        assert_eq!(Calibration::key(&first), "senseiten@0123");
+        assert_eq!(Calibration::key(&anonymous), "senseiten");
+
+        let dimmed = [[0.9, 0.0, 0.0], [0.0, 0.8, 0.0], [0.0, 0.0, 0.7]];
+        let mut calibration = Calibration::new();
+        calibration.set(&first, dimmed);
+        assert_eq!(calibration.get(&first), Some(dimmed));
+        assert_eq!(calibration.get(&second), None);
+
+        // The model calibration applies to the devices without one of their own
+        calibration.set(&anonymous, IDENTITY_MATRIX);
+        assert_eq!(calibration.get(&second), Some(IDENTITY_MATRIX));
+        assert_eq!(calibration.get(&first), Some(dimmed));
+
+        let source = calibration.to_toml().unwrap();
+        assert!(source.contains("[\"senseiten@0123\"]"));
+        assert_eq!(Calibration::from_toml(&source).unwrap(), calibration);
+
+        assert!(calibration.remove(&first));
+        assert!(!calibration.remove(&first));
+        assert_eq!(calibration.get(&first), Some(IDENTITY_MATRIX));
+    }
+}
@@ -0,0 +1,435 @@
+//# Recording and replaying of HID traffic.
+//#
+//# A capture is a plain text file, one event per line, prefixed by the milliseconds
+//# elapsed since the recording started:
+//#
+//#     # steelcli capture
+//#     # started 1697450000
+//#     0 device 1038:1832 /dev/hidraw3 - 1 ffc0:0001
+//#     12 /dev/hidraw3 feature 5b00...
+//#     15 /dev/hidraw3 write 5900
+//#     18 /dev/hidraw3 get-feature 5b
+//#     19 /dev/hidraw3 reply 5b00...
+//#
+//# Device lines hold the serial number (`-` if unknown), the interface number and the
+//# usage page and usage. The last two are optional. Whitespace and `%` in paths and
+//# serial numbers are written as `%` followed by two hex digits, as in `%20` for a space.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    rc::Rc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use super::{Backend, Error, HidDeviceInfo, Result, Transport, STEELSERIES_VENDOR_ID};
+use crate::utils::{from_hex, to_hex};
+
+#[derive(Debug, PartialEq, Clone)]
+enum CaptureEvent {
+    Write(Vec<u8>),
+    FeatureReport(Vec<u8>),
+    GetFeatureReport(u8),
+    Reply(Vec<u8>),
+    Error,
+}
+
+impl CaptureEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            CaptureEvent::Write(_) => "write",
+            CaptureEvent::FeatureReport(_) => "feature",
+            CaptureEvent::GetFeatureReport(_) => "get-feature",
+            CaptureEvent::Reply(_) => "reply",
+            CaptureEvent::Error => "error",
+        }
+    }
+
+    fn payload(&self) -> String {
+        match self {
+            CaptureEvent::Write(data)
+            | CaptureEvent::FeatureReport(data)
+            | CaptureEvent::Reply(data) => to_hex(data),
+            CaptureEvent::GetFeatureReport(report_id) => to_hex(&[*report_id]),
+            CaptureEvent::Error => String::new(),
+        }
+    }
+
+    fn parse(kind: &str, payload: &str) -> Option<Self> {
+        match kind {
+            "write" => Some(CaptureEvent::Write(from_hex(payload)?)),
+            "feature" => Some(CaptureEvent::FeatureReport(from_hex(payload)?)),
+            "get-feature" => Some(CaptureEvent::GetFeatureReport(
+                *from_hex(payload)?.first()?,
+            )),
+            "reply" => Some(CaptureEvent::Reply(from_hex(payload)?)),
+            "error" => Some(CaptureEvent::Error),
+            _ => None,
+        }
+    }
+}
+
+struct CaptureWriter {
+    file: File,
+    started: Instant,
+    known_devices: Vec<String>,
+}
+
+impl CaptureWriter {
+    fn line(&mut self, line: String) -> Result<()> {
+        let elapsed = self.started.elapsed().as_millis();
+        writeln!(self.file, "{} {}", elapsed, line.trim_end())?;
+        Ok(())
+    }
+
+    fn device(&mut self, info: &HidDeviceInfo) -> Result<()> {
+        if self.known_devices.contains(&info.path) {
+            return Ok(());
+        }
+
+        self.known_devices.push(info.path.clone());
+        self.line(format!(
+            "device {:04x}:{:04x} {} {} {} {:04x}:{:04x}",
+            info.vendor_id,
+            info.product_id,
+            escape(info.path.as_str()),
+            info.serial_number
+                .as_deref()
+                .map(escape)
+                .unwrap_or_else(|| "-".to_string()),
+            info.interface_number,
+            info.usage_page,
+            info.usage
+        ))
+    }
+
+    fn event(&mut self, path: &str, event: &CaptureEvent) -> Result<()> {
+        self.line(format!(
+            "{} {} {}",
+            escape(path),
+            event.kind(),
+            event.payload()
+        ))
+    }
+}
+
+/// Escapes the characters of a capture field that would end it or be mistaken for an escape,
+/// along with a lone `-`, which stands for a missing field
+fn escape(field: &str) -> String {
+    if field == "-" {
+        return String::from("%2d");
+    }
+
+    field
+        .chars()
+        .map(|c| match c {
+            c if c == '%' || c.is_whitespace() => c
+                .to_string()
+                .bytes()
+                .map(|b| format!("%{:02x}", b))
+                .collect(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// Reverts `escape`, `None` if `field` holds an invalid escape
+fn unescape(field: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut rest = field.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+/// A backend that forwards everything to another backend, logging every packet
+/// sent and every reply received to a capture file
+pub struct RecordingBackend {
+    inner: Box<dyn Backend>,
+    writer: Rc<RefCell<CaptureWriter>>,
+}
+
+impl RecordingBackend {
+    pub fn new<P: AsRef<Path>>(inner: Box<dyn Backend>, capture: P) -> Result<Self> {
+        let mut file = File::create(capture)?;
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        writeln!(file, "# steelcli capture")?;
+        writeln!(file, "# started {}", started_at)?;
+
+        Ok(Self {
+            inner,
+            writer: Rc::new(RefCell::new(CaptureWriter {
+                file,
+                started: Instant::now(),
+                known_devices: vec![],
+            })),
+        })
+    }
+}
+
+impl Backend for RecordingBackend {
+    fn device_list(&self) -> Vec<HidDeviceInfo> {
+        let devices = self.inner.device_list();
+        let mut writer = self.writer.borrow_mut();
+        for device in devices
+            .iter()
+            .filter(|d| d.vendor_id == STEELSERIES_VENDOR_ID)
+        {
+            if let Err(e) = writer.device(device) {
+                log::warn!("Cannot write to capture file: {:?}", e);
+            }
+        }
+
+        devices
+    }
+
+    fn open(&self, info: &HidDeviceInfo) -> Result<Box<dyn Transport>> {
+        self.writer.borrow_mut().device(info)?;
+        let transport = self.inner.open(info)?;
+        Ok(Box::new(RecordingTransport {
+            path: info.path.clone(),
+            inner: transport,
+            writer: Rc::clone(&self.writer),
+        }))
+    }
+
+    fn refresh(&self) -> Result<()> {
+        self.inner.refresh()
+    }
+}
+
+struct RecordingTransport {
+    path: String,
+    inner: Box<dyn Transport>,
+    writer: Rc<RefCell<CaptureWriter>>,
+}
+
+impl RecordingTransport {
+    fn record(&self, event: CaptureEvent) -> Result<()> {
+        self.writer.borrow_mut().event(self.path.as_str(), &event)
+    }
+
+    fn record_reply(&self, result: &Result<usize>, buf: &[u8]) -> Result<()> {
+        match result {
+            Ok(len) => self.record(CaptureEvent::Reply(buf[..*len].to_vec())),
+            Err(_) => self.record(CaptureEvent::Error),
+        }
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        self.record(CaptureEvent::Write(buf.to_vec()))?;
+        self.inner.write(buf)
+    }
+
+    fn send_feature_report(&self, buf: &[u8]) -> Result<()> {
+        self.record(CaptureEvent::FeatureReport(buf.to_vec()))?;
+        self.inner.send_feature_report(buf)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        self.record(CaptureEvent::GetFeatureReport(buf[0]))?;
+        let result = self.inner.get_feature_report(buf);
+        self.record_reply(&result, buf)?;
+        result
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> Result<usize> {
+        let result = self.inner.read_timeout(buf, timeout);
+        self.record_reply(&result, buf)?;
+        result
+    }
+}
+
+/// A backend that plays a capture file back as if it were the devices it was
+/// recorded from. Packets sent must match the recorded ones, in order.
+pub struct ReplayBackend {
+    devices: Vec<HidDeviceInfo>,
+    events: HashMap<String, Rc<RefCell<VecDeque<CaptureEvent>>>>,
+}
+
+impl ReplayBackend {
+    pub fn new<P: AsRef<Path>>(capture: P) -> Result<Self> {
+        let reader = BufReader::new(File::open(capture)?);
+        let mut devices = vec![];
+        let mut events: HashMap<String, Rc<RefCell<VecDeque<CaptureEvent>>>> = HashMap::new();
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || Error::Replay(format!("Invalid capture line {}: {}", index + 1, line));
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [_elapsed, "device", id, path, serial, interface @ ..] if interface.len() <= 2 => {
+                    let (vendor_id, product_id) =
+                        crate::utils::parse_device_id(id).ok_or_else(invalid)?;
+                    let path = unescape(path).ok_or_else(invalid)?;
+                    let mut info = HidDeviceInfo::new(vendor_id, product_id, path.as_str());
+                    info.serial_number = match *serial {
+                        "-" => None,
+                        serial => Some(unescape(serial).ok_or_else(invalid)?),
+                    };
+                    if let [interface_number, usage @ ..] = interface {
+                        info.interface_number = interface_number.parse().map_err(|_| invalid())?;
+                        if let Some(usage) = usage.first() {
+                            let (usage_page, usage) =
+                                crate::utils::parse_device_id(usage).ok_or_else(invalid)?;
+                            info = info.with_usage(usage_page, usage);
+                        }
+                    }
+                    devices.push(info);
+                }
+                [_elapsed, path, kind, payload @ ..] => {
+                    let event = CaptureEvent::parse(kind, payload.first().unwrap_or(&""))
+                        .ok_or_else(invalid)?;
+                    events
+                        .entry(unescape(path).ok_or_else(invalid)?)
+                        .or_default()
+                        .borrow_mut()
+                        .push_back(event);
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(Self { devices, events })
+    }
+}
+
+impl Backend for ReplayBackend {
+    fn device_list(&self) -> Vec<HidDeviceInfo> {
+        self.devices.clone()
+    }
+
+    fn open(&self, info: &HidDeviceInfo) -> Result<Box<dyn Transport>> {
+        if !self.devices.iter().any(|d| d.path == info.path) {
+            return Err(Error::OpenDevice);
+        }
+
+        let events = self.events.get(&info.path).cloned().unwrap_or_default();
+        Ok(Box::new(ReplayTransport { events }))
+    }
+}
+
+struct ReplayTransport {
+    events: Rc<RefCell<VecDeque<CaptureEvent>>>,
+}
+
+impl ReplayTransport {
+    fn expect(&self, sent: CaptureEvent) -> Result<()> {
+        match self.events.borrow_mut().pop_front() {
+            Some(recorded) if recorded == sent => Ok(()),
+            Some(recorded) => Err(Error::Replay(format!(
+                "Expected {} {}, got {} {}",
+                recorded.kind(),
+                recorded.payload(),
+                sent.kind(),
+                sent.payload()
+            ))),
+            None => Err(Error::Replay(format!(
+                "Unexpected {} {} past the end of the capture",
+                sent.kind(),
+                sent.payload()
+            ))),
+        }
+    }
+
+    fn reply(&self, buf: &mut [u8]) -> Result<usize> {
+        match self.events.borrow_mut().pop_front() {
+            Some(CaptureEvent::Reply(data)) => {
+                let len = data.len().min(buf.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                Ok(len)
+            }
+            Some(CaptureEvent::Error) => Err(Error::UsbComm),
+            Some(other) => Err(Error::Replay(format!(
+                "Expected {} {}, got a read",
+                other.kind(),
+                other.payload()
+            ))),
+            None => Err(Error::Replay(String::from(
+                "Unexpected read past the end of the capture",
+            ))),
+        }
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        self.expect(CaptureEvent::Write(buf.to_vec()))?;
+        Ok(buf.len())
+    }
+
+    fn send_feature_report(&self, buf: &[u8]) -> Result<()> {
+        self.expect(CaptureEvent::FeatureReport(buf.to_vec()))
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        self.expect(CaptureEvent::GetFeatureReport(buf[0]))?;
+        self.reply(buf)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], _timeout: i32) -> Result<usize> {
+        self.reply(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RecordingBackend, ReplayBackend};
+    use crate::steelseries_core::{Backend, Error, HidDeviceInfo, MemoryBackend};
+
+    #[test]
+    fn record_then_replay() {
+        let capture = std::env::temp_dir().join(format!(
+            "steelcli-record-then-replay-{}.capture",
+            std::process::id()
+        ));
+        let mut info = HidDeviceInfo::new(0x1038, 0x1832, "IOService:/Sensei Ten@1/100%");
+        info.serial_number = Some(String::from("AB 12"));
+        let memory = MemoryBackend::new().with_info(info);
+        memory.transport().push_reply(&[0x5b, 0x01, 0x02]);
+
+        let recording = RecordingBackend::new(Box::new(memory), &capture).unwrap();
+        let device = recording.device_list().remove(0);
+        let transport = recording.open(&device).unwrap();
+        transport.send_feature_report(&[0x5b, 0x00, 0xff]).unwrap();
+        let mut buf = [0x5b, 0x00, 0x00];
+        transport.get_feature_report(&mut buf).unwrap();
+        drop(transport);
+
+        let replay = ReplayBackend::new(&capture).unwrap();
+        assert_eq!(replay.device_list(), vec![device.clone()]);
+        let transport = replay.open(&device).unwrap();
+        transport.send_feature_report(&[0x5b, 0x00, 0xff]).unwrap();
+        let mut buf = [0x5b, 0x00, 0x00];
+        assert_eq!(transport.get_feature_report(&mut buf).unwrap(), 3);
+        assert_eq!(buf, [0x5b, 0x01, 0x02]);
+        assert!(matches!(
+            transport.write(&[0x59, 0x00]),
+            Err(Error::Replay(_))
+        ));
+
+        std::fs::remove_file(capture).unwrap();
+    }
+}
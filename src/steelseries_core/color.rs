@@ -1,33 +1,177 @@
-use std::{collections::HashMap, convert::TryFrom, slice::Iter, time::Duration};
-use super::Error;
+use std::{
+    collections::HashMap, convert::TryFrom, fmt::Display, ops::Range, slice::Iter, str::FromStr,
+    time::Duration,
+};
+use super::{ConversionError, Error};
 
 lazy_static! {
+    /// The CSS Color Level 4 named colours
     pub static ref NAMED_COLORS: HashMap<&'static str, Color> = {
         let mut map = HashMap::new();
-        map.insert("white", Color::from((0xFF, 0xFF, 0xFF)));
-        map.insert("silver", Color::from((0xC0, 0xC0, 0xC0)));
-        map.insert("gray", Color::from((0x80, 0x80, 0x80)));
-        map.insert("black", Color::from((0x00, 0x00, 0x00)));
-        map.insert("red", Color::from((0xFF, 0x00, 0x00)));
-        map.insert("maroon", Color::from((0x80, 0x00, 0x00)));
-        map.insert("yellow", Color::from((0xFF, 0xFF, 0x00)));
-        map.insert("olive", Color::from((0x80, 0x80, 0x00)));
-        map.insert("lime", Color::from((0x00, 0xFF, 0x00)));
-        map.insert("green", Color::from((0x00, 0x80, 0x00)));
+        map.insert("aliceblue", Color::from((0xF0, 0xF8, 0xFF)));
+        map.insert("antiquewhite", Color::from((0xFA, 0xEB, 0xD7)));
         map.insert("aqua", Color::from((0x00, 0xFF, 0xFF)));
-        map.insert("teal", Color::from((0x00, 0x80, 0x80)));
+        map.insert("aquamarine", Color::from((0x7F, 0xFF, 0xD4)));
+        map.insert("azure", Color::from((0xF0, 0xFF, 0xFF)));
+        map.insert("beige", Color::from((0xF5, 0xF5, 0xDC)));
+        map.insert("bisque", Color::from((0xFF, 0xE4, 0xC4)));
+        map.insert("black", Color::from((0x00, 0x00, 0x00)));
+        map.insert("blanchedalmond", Color::from((0xFF, 0xEB, 0xCD)));
         map.insert("blue", Color::from((0x00, 0x00, 0xFF)));
-        map.insert("navy", Color::from((0x00, 0x00, 0x80)));
+        map.insert("blueviolet", Color::from((0x8A, 0x2B, 0xE2)));
+        map.insert("brown", Color::from((0xA5, 0x2A, 0x2A)));
+        map.insert("burlywood", Color::from((0xDE, 0xB8, 0x87)));
+        map.insert("cadetblue", Color::from((0x5F, 0x9E, 0xA0)));
+        map.insert("chartreuse", Color::from((0x7F, 0xFF, 0x00)));
+        map.insert("chocolate", Color::from((0xD2, 0x69, 0x1E)));
+        map.insert("coral", Color::from((0xFF, 0x7F, 0x50)));
+        map.insert("cornflowerblue", Color::from((0x64, 0x95, 0xED)));
+        map.insert("cornsilk", Color::from((0xFF, 0xF8, 0xDC)));
+        map.insert("crimson", Color::from((0xDC, 0x14, 0x3C)));
+        map.insert("cyan", Color::from((0x00, 0xFF, 0xFF)));
+        map.insert("darkblue", Color::from((0x00, 0x00, 0x8B)));
+        map.insert("darkcyan", Color::from((0x00, 0x8B, 0x8B)));
+        map.insert("darkgoldenrod", Color::from((0xB8, 0x86, 0x0B)));
+        map.insert("darkgray", Color::from((0xA9, 0xA9, 0xA9)));
+        map.insert("darkgreen", Color::from((0x00, 0x64, 0x00)));
+        map.insert("darkgrey", Color::from((0xA9, 0xA9, 0xA9)));
+        map.insert("darkkhaki", Color::from((0xBD, 0xB7, 0x6B)));
+        map.insert("darkmagenta", Color::from((0x8B, 0x00, 0x8B)));
+        map.insert("darkolivegreen", Color::from((0x55, 0x6B, 0x2F)));
+        map.insert("darkorange", Color::from((0xFF, 0x8C, 0x00)));
+        map.insert("darkorchid", Color::from((0x99, 0x32, 0xCC)));
+        map.insert("darkred", Color::from((0x8B, 0x00, 0x00)));
+        map.insert("darksalmon", Color::from((0xE9, 0x96, 0x7A)));
+        map.insert("darkseagreen", Color::from((0x8F, 0xBC, 0x8F)));
+        map.insert("darkslateblue", Color::from((0x48, 0x3D, 0x8B)));
+        map.insert("darkslategray", Color::from((0x2F, 0x4F, 0x4F)));
+        map.insert("darkslategrey", Color::from((0x2F, 0x4F, 0x4F)));
+        map.insert("darkturquoise", Color::from((0x00, 0xCE, 0xD1)));
+        map.insert("darkviolet", Color::from((0x94, 0x00, 0xD3)));
+        map.insert("deeppink", Color::from((0xFF, 0x14, 0x93)));
+        map.insert("deepskyblue", Color::from((0x00, 0xBF, 0xFF)));
+        map.insert("dimgray", Color::from((0x69, 0x69, 0x69)));
+        map.insert("dimgrey", Color::from((0x69, 0x69, 0x69)));
+        map.insert("dodgerblue", Color::from((0x1E, 0x90, 0xFF)));
+        map.insert("firebrick", Color::from((0xB2, 0x22, 0x22)));
+        map.insert("floralwhite", Color::from((0xFF, 0xFA, 0xF0)));
+        map.insert("forestgreen", Color::from((0x22, 0x8B, 0x22)));
         map.insert("fuchsia", Color::from((0xFF, 0x00, 0xFF)));
+        map.insert("gainsboro", Color::from((0xDC, 0xDC, 0xDC)));
+        map.insert("ghostwhite", Color::from((0xF8, 0xF8, 0xFF)));
+        map.insert("gold", Color::from((0xFF, 0xD7, 0x00)));
+        map.insert("goldenrod", Color::from((0xDA, 0xA5, 0x20)));
+        map.insert("gray", Color::from((0x80, 0x80, 0x80)));
+        map.insert("green", Color::from((0x00, 0x80, 0x00)));
+        map.insert("greenyellow", Color::from((0xAD, 0xFF, 0x2F)));
+        map.insert("grey", Color::from((0x80, 0x80, 0x80)));
+        map.insert("honeydew", Color::from((0xF0, 0xFF, 0xF0)));
+        map.insert("hotpink", Color::from((0xFF, 0x69, 0xB4)));
+        map.insert("indianred", Color::from((0xCD, 0x5C, 0x5C)));
+        map.insert("indigo", Color::from((0x4B, 0x00, 0x82)));
+        map.insert("ivory", Color::from((0xFF, 0xFF, 0xF0)));
+        map.insert("khaki", Color::from((0xF0, 0xE6, 0x8C)));
+        map.insert("lavender", Color::from((0xE6, 0xE6, 0xFA)));
+        map.insert("lavenderblush", Color::from((0xFF, 0xF0, 0xF5)));
+        map.insert("lawngreen", Color::from((0x7C, 0xFC, 0x00)));
+        map.insert("lemonchiffon", Color::from((0xFF, 0xFA, 0xCD)));
+        map.insert("lightblue", Color::from((0xAD, 0xD8, 0xE6)));
+        map.insert("lightcoral", Color::from((0xF0, 0x80, 0x80)));
+        map.insert("lightcyan", Color::from((0xE0, 0xFF, 0xFF)));
+        map.insert("lightgoldenrodyellow", Color::from((0xFA, 0xFA, 0xD2)));
+        map.insert("lightgray", Color::from((0xD3, 0xD3, 0xD3)));
+        map.insert("lightgreen", Color::from((0x90, 0xEE, 0x90)));
+        map.insert("lightgrey", Color::from((0xD3, 0xD3, 0xD3)));
+        map.insert("lightpink", Color::from((0xFF, 0xB6, 0xC1)));
+        map.insert("lightsalmon", Color::from((0xFF, 0xA0, 0x7A)));
+        map.insert("lightseagreen", Color::from((0x20, 0xB2, 0xAA)));
+        map.insert("lightskyblue", Color::from((0x87, 0xCE, 0xFA)));
+        map.insert("lightslategray", Color::from((0x77, 0x88, 0x99)));
+        map.insert("lightslategrey", Color::from((0x77, 0x88, 0x99)));
+        map.insert("lightsteelblue", Color::from((0xB0, 0xC4, 0xDE)));
+        map.insert("lightyellow", Color::from((0xFF, 0xFF, 0xE0)));
+        map.insert("lime", Color::from((0x00, 0xFF, 0x00)));
+        map.insert("limegreen", Color::from((0x32, 0xCD, 0x32)));
+        map.insert("linen", Color::from((0xFA, 0xF0, 0xE6)));
+        map.insert("magenta", Color::from((0xFF, 0x00, 0xFF)));
+        map.insert("maroon", Color::from((0x80, 0x00, 0x00)));
+        map.insert("mediumaquamarine", Color::from((0x66, 0xCD, 0xAA)));
+        map.insert("mediumblue", Color::from((0x00, 0x00, 0xCD)));
+        map.insert("mediumorchid", Color::from((0xBA, 0x55, 0xD3)));
+        map.insert("mediumpurple", Color::from((0x93, 0x70, 0xDB)));
+        map.insert("mediumseagreen", Color::from((0x3C, 0xB3, 0x71)));
+        map.insert("mediumslateblue", Color::from((0x7B, 0x68, 0xEE)));
+        map.insert("mediumspringgreen", Color::from((0x00, 0xFA, 0x9A)));
+        map.insert("mediumturquoise", Color::from((0x48, 0xD1, 0xCC)));
+        map.insert("mediumvioletred", Color::from((0xC7, 0x15, 0x85)));
+        map.insert("midnightblue", Color::from((0x19, 0x19, 0x70)));
+        map.insert("mintcream", Color::from((0xF5, 0xFF, 0xFA)));
+        map.insert("mistyrose", Color::from((0xFF, 0xE4, 0xE1)));
+        map.insert("moccasin", Color::from((0xFF, 0xE4, 0xB5)));
+        map.insert("navajowhite", Color::from((0xFF, 0xDE, 0xAD)));
+        map.insert("navy", Color::from((0x00, 0x00, 0x80)));
+        map.insert("oldlace", Color::from((0xFD, 0xF5, 0xE6)));
+        map.insert("olive", Color::from((0x80, 0x80, 0x00)));
+        map.insert("olivedrab", Color::from((0x6B, 0x8E, 0x23)));
+        map.insert("orange", Color::from((0xFF, 0xA5, 0x00)));
+        map.insert("orangered", Color::from((0xFF, 0x45, 0x00)));
+        map.insert("orchid", Color::from((0xDA, 0x70, 0xD6)));
+        map.insert("palegoldenrod", Color::from((0xEE, 0xE8, 0xAA)));
+        map.insert("palegreen", Color::from((0x98, 0xFB, 0x98)));
+        map.insert("paleturquoise", Color::from((0xAF, 0xEE, 0xEE)));
+        map.insert("palevioletred", Color::from((0xDB, 0x70, 0x93)));
+        map.insert("papayawhip", Color::from((0xFF, 0xEF, 0xD5)));
+        map.insert("peachpuff", Color::from((0xFF, 0xDA, 0xB9)));
+        map.insert("peru", Color::from((0xCD, 0x85, 0x3F)));
+        map.insert("pink", Color::from((0xFF, 0xC0, 0xCB)));
+        map.insert("plum", Color::from((0xDD, 0xA0, 0xDD)));
+        map.insert("powderblue", Color::from((0xB0, 0xE0, 0xE6)));
         map.insert("purple", Color::from((0x80, 0x00, 0x80)));
-        map.insert("orange", Color::from((0xCC, 0x3B, 0x02)));
+        map.insert("rebeccapurple", Color::from((0x66, 0x33, 0x99)));
+        map.insert("red", Color::from((0xFF, 0x00, 0x00)));
+        map.insert("rosybrown", Color::from((0xBC, 0x8F, 0x8F)));
+        map.insert("royalblue", Color::from((0x41, 0x69, 0xE1)));
+        map.insert("saddlebrown", Color::from((0x8B, 0x45, 0x13)));
+        map.insert("salmon", Color::from((0xFA, 0x80, 0x72)));
+        map.insert("sandybrown", Color::from((0xF4, 0xA4, 0x60)));
+        map.insert("seagreen", Color::from((0x2E, 0x8B, 0x57)));
+        map.insert("seashell", Color::from((0xFF, 0xF5, 0xEE)));
+        map.insert("sienna", Color::from((0xA0, 0x52, 0x2D)));
+        map.insert("silver", Color::from((0xC0, 0xC0, 0xC0)));
+        map.insert("skyblue", Color::from((0x87, 0xCE, 0xEB)));
+        map.insert("slateblue", Color::from((0x6A, 0x5A, 0xCD)));
+        map.insert("slategray", Color::from((0x70, 0x80, 0x90)));
+        map.insert("slategrey", Color::from((0x70, 0x80, 0x90)));
+        map.insert("snow", Color::from((0xFF, 0xFA, 0xFA)));
+        map.insert("springgreen", Color::from((0x00, 0xFF, 0x7F)));
+        map.insert("steelblue", Color::from((0x46, 0x82, 0xB4)));
+        map.insert("tan", Color::from((0xD2, 0xB4, 0x8C)));
+        map.insert("teal", Color::from((0x00, 0x80, 0x80)));
+        map.insert("thistle", Color::from((0xD8, 0xBF, 0xD8)));
+        map.insert("tomato", Color::from((0xFF, 0x63, 0x47)));
+        map.insert("turquoise", Color::from((0x40, 0xE0, 0xD0)));
+        map.insert("violet", Color::from((0xEE, 0x82, 0xEE)));
+        map.insert("wheat", Color::from((0xF5, 0xDE, 0xB3)));
+        map.insert("white", Color::from((0xFF, 0xFF, 0xFF)));
+        map.insert("whitesmoke", Color::from((0xF5, 0xF5, 0xF5)));
+        map.insert("yellow", Color::from((0xFF, 0xFF, 0x00)));
+        map.insert("yellowgreen", Color::from((0x9A, 0xCD, 0x32)));
 
         map
     };
 }
 
+/// The duration of gradients given without one
 const DEFAULT_DURATION: Duration = Duration::from_secs(1);
 
+/// Colour temperatures accepted as `2700K`, in kelvins
+const TEMPERATURE_RANGE: std::ops::RangeInclusive<u32> = 1000..=40000;
+
+/// Names of the buttons that can trigger a gradient, by bit of the triggers field
+const TRIGGER_NAMES: [&str; 8] = [
+    "click", "right", "middle", "back", "forward", "button6", "button7", "button8",
+];
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Color {
     red: u8,
@@ -47,6 +191,179 @@ impl Color {
     pub fn blue(&self) -> u8 {
         self.blue
     }
+
+    /// This colour with every channel multiplied by `factor`, between 0 and 1
+    pub fn scaled(&self, factor: f64) -> Self {
+        let scale = |channel: u8| (channel as f64 * factor.clamp(0.0, 1.0)).round() as u8;
+        Self {
+            red: scale(self.red),
+            green: scale(self.green),
+            blue: scale(self.blue),
+        }
+    }
+
+    /// The colour `t` of the way from this colour to `other`, `t` being between 0 and 1
+    pub fn mix(&self, other: &Color, t: f64) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |from: u8, to: u8| (from as f64 + (to as f64 - from as f64) * t).round() as u8;
+        Self {
+            red: mix(self.red, other.red),
+            green: mix(self.green, other.green),
+            blue: mix(self.blue, other.blue),
+        }
+    }
+
+    /// Converts hue `h` in degrees, saturation `s` and lightness `l` between 0 and 1
+    pub fn from_hsl(h: f64, s: f64, l: f64) -> Self {
+        let (s, l) = (s.clamp(0.0, 1.0), l.clamp(0.0, 1.0));
+        let a = s * l.min(1.0 - l);
+        let channel = |n: f64| {
+            let k = (n + h.rem_euclid(360.0) / 30.0) % 12.0;
+            to_byte(l - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0))
+        };
+        Color::from((channel(0.0), channel(8.0), channel(4.0)))
+    }
+
+    /// Converts hue `h` in degrees, saturation `s` and value `v` between 0 and 1
+    pub fn from_hsv(h: f64, s: f64, v: f64) -> Self {
+        let (s, v) = (s.clamp(0.0, 1.0), v.clamp(0.0, 1.0));
+        let channel = |n: f64| {
+            let k = (n + h.rem_euclid(360.0) / 60.0) % 6.0;
+            to_byte(v - v * s * k.min(4.0 - k).clamp(0.0, 1.0))
+        };
+        Color::from((channel(5.0), channel(3.0), channel(1.0)))
+    }
+
+    /// The colour of a black body at `kelvin`, as approximated by Tanner Helland's fit of
+    /// the CIE 1964 data. 6600K is about white, lower is warmer and higher is bluer.
+    pub fn from_temperature(kelvin: u32) -> Self {
+        let t = kelvin as f64 / 100.0;
+        let red = match t <= 66.0 {
+            true => 255.0,
+            false => 329.698727446 * (t - 60.0).powf(-0.1332047592),
+        };
+        let green = match t <= 66.0 {
+            true => 99.4708025861 * t.ln() - 161.1195681661,
+            false => 288.1221695283 * (t - 60.0).powf(-0.0755148492),
+        };
+        let blue = match t {
+            t if t >= 66.0 => 255.0,
+            t if t <= 19.0 => 0.0,
+            t => 138.5177312231 * (t - 10.0).ln() - 305.0447927307,
+        };
+        let channel = |value: f64| to_byte(value / 255.0);
+        Color::from((channel(red), channel(green), channel(blue)))
+    }
+}
+
+/// Converts a channel between 0 and 1 to a byte
+fn to_byte(value: f64) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// A 3 by 3 matrix mixing the red, green and blue channels, by row of output channel
+pub type ColorMatrix = [[f64; 3]; 3];
+
+/// The matrix leaving colours unchanged
+pub const IDENTITY_MATRIX: ColorMatrix = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+/// How colours are adjusted on their way to a device, so that LEDs can be dimmed and the same
+/// colour looks alike on every device. Channels, between 0 and 1, are scaled by `brightness`,
+/// raised to the power `gamma`, mixed by the calibration `matrix` and finally scaled by the
+/// white `balance`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorCorrection {
+    pub brightness: f64,
+    /// Exponent of the red, green and blue channels
+    pub gamma: [f64; 3],
+    /// Calibration of the device itself, see `Calibration`
+    pub matrix: ColorMatrix,
+    /// Output of the red, green and blue channels for white
+    pub balance: [f64; 3],
+}
+
+impl Default for ColorCorrection {
+    fn default() -> Self {
+        Self {
+            brightness: 1.0,
+            gamma: [1.0; 3],
+            matrix: IDENTITY_MATRIX,
+            balance: [1.0; 3],
+        }
+    }
+}
+
+impl ColorCorrection {
+    /// The colour to send to the device for `color` to show
+    pub fn apply(&self, color: &Color) -> Color {
+        let channels: [u8; 3] = color.clone().into();
+        let brightness = self.brightness.clamp(0.0, 1.0);
+        let mut drive = [0.0; 3];
+        for i in 0..3 {
+            drive[i] = (channels[i] as f64 / 255.0 * brightness).powf(self.gamma[i]);
+        }
+
+        let mixed = multiply(&self.matrix, drive);
+        Color::from((
+            to_byte(mixed[0] * self.balance[0]),
+            to_byte(mixed[1] * self.balance[1]),
+            to_byte(mixed[2] * self.balance[2]),
+        ))
+    }
+
+    /// The colour showing when the device was sent `color`, as far as it can be told back.
+    /// Channels clipped by `apply` stay clipped and a zero brightness gives black.
+    pub fn revert(&self, color: &Color) -> Color {
+        let channels: [u8; 3] = color.clone().into();
+        let mut drive = [0.0; 3];
+        for i in 0..3 {
+            if self.balance[i] > 0.0 {
+                drive[i] = channels[i] as f64 / 255.0 / self.balance[i];
+            }
+        }
+
+        // A matrix that can not be inverted is ignored rather than giving nonsense
+        let drive = invert(&self.matrix).map_or(drive, |inverse| multiply(&inverse, drive));
+        let channel = |i: usize| {
+            if self.gamma[i] <= 0.0 || self.brightness <= 0.0 {
+                return 0x00;
+            }
+            to_byte(drive[i].max(0.0).powf(1.0 / self.gamma[i]) / self.brightness.min(1.0))
+        };
+        Color::from((channel(0), channel(1), channel(2)))
+    }
+}
+
+fn multiply(matrix: &ColorMatrix, channels: [f64; 3]) -> [f64; 3] {
+    let row = |r: &[f64; 3]| r[0] * channels[0] + r[1] * channels[1] + r[2] * channels[2];
+    [row(&matrix[0]), row(&matrix[1]), row(&matrix[2])]
+}
+
+/// The inverse of `m`, `None` if its determinant is zero
+fn invert(m: &ColorMatrix) -> Option<ColorMatrix> {
+    let cofactor = |r: usize, c: usize| {
+        let (r1, r2) = ((r + 1) % 3, (r + 2) % 3);
+        let (c1, c2) = ((c + 1) % 3, (c + 2) % 3);
+        m[r1][c1] * m[r2][c2] - m[r1][c2] * m[r2][c1]
+    };
+    let determinant = (0..3).map(|c| m[0][c] * cofactor(0, c)).sum::<f64>();
+    if determinant.abs() < f64::EPSILON {
+        return None;
+    }
+
+    let mut inverse = [[0.0; 3]; 3];
+    for (r, row) in inverse.iter_mut().enumerate() {
+        for (c, value) in row.iter_mut().enumerate() {
+            *value = cofactor(c, r) / determinant;
+        }
+    }
+    Some(inverse)
+}
+
+impl Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
+    }
 }
 
 impl From<(u8, u8, u8)> for Color {
@@ -59,15 +376,15 @@ impl From<(u8, u8, u8)> for Color {
     }
 }
 
-impl Into<(u8, u8, u8)> for Color {
-    fn into(self) -> (u8, u8, u8) {
-        (self.red(), self.green(), self.blue())
+impl From<Color> for (u8, u8, u8) {
+    fn from(color: Color) -> Self {
+        (color.red(), color.green(), color.blue())
     }
 }
 
-impl Into<[u8; 3]> for Color {
-    fn into(self) -> [u8; 3] {
-        [self.red(), self.green(), self.blue()]
+impl From<Color> for [u8; 3] {
+    fn from(color: Color) -> Self {
+        [color.red(), color.green(), color.blue()]
     }
 }
 
@@ -81,75 +398,409 @@ impl From<&[u8]> for Color {
     }
 }
 
-impl From<&str> for Color {
-    fn from(s: &str) -> Self {
-        let hex_regex = regex::Regex::new(r"^[a-fA-F0-9]{6}$").unwrap();
-
-        if NAMED_COLORS.contains_key(s) {
-            NAMED_COLORS.get(s).unwrap().clone()
-        } else if hex_regex.is_match(s) {
-            let hex_channels = s.replacen("#", "", 1);
-            let hex_channels = hex_channels.trim();
-            Self {
-                red: u8::from_str_radix(&hex_channels[0..2], 16)
-                    .expect("Cant't read RED channel")
-                    .wrapping_shr(16)
-                    & 255u8,
-                green: u8::from_str_radix(&hex_channels[2..4], 16)
-                    .expect("Cant't read GREEN channel")
-                    .wrapping_shr(8)
-                    & 255u8,
-                blue: u8::from_str_radix(&hex_channels[4..6], 16)
-                    .expect("Cant't read BLUE channel")
-                    & 255,
+/// Parses a colour given by name (in any case, from the user palette or the CSS colours), as
+/// `#rgb`, `#rrggbb`, `rgb(r, g, b)`, `r,g,b`, `hsl(h, s%, l%)`, `hsv(h, s%, v%)` or as a colour
+/// temperature such as `2700K`. Channels of `rgb()` can also be percentages. Errors point at
+/// the offending part of `raw`.
+impl FromStr for Color {
+    type Err = Error;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let trimmed = raw.trim();
+        let start = raw.len() - raw.trim_start().len();
+        let end = start + trimmed.len();
+        let fail = |span: Range<usize>, hint: &str| ConversionError::new(raw, span, hint).into();
+        if trimmed.is_empty() {
+            return Err(fail(0..raw.len(), "expected a colour"));
+        }
+
+        if let Some(color) = super::lookup_color(trimmed) {
+            return Ok(color);
+        }
+
+        if let Some(digits) = trimmed.strip_prefix('#') {
+            return parse_hex(digits).map_err(|e| e.within(raw, start + 1).into());
+        }
+
+        if let Some(open) = trimmed.find('(') {
+            let args = match trimmed[open + 1..].strip_suffix(')') {
+                Some(args) => args,
+                None => return Err(fail(end..end, "expected `)` at the end")),
+            };
+            let offset = start + open + 1;
+            let parsed = match trimmed[..open].trim().to_lowercase().as_str() {
+                "rgb" => parse_channels(args),
+                "hsl" => parse_hue(args).map(|(h, s, l)| Color::from_hsl(h, s, l)),
+                "hsv" => parse_hue(args).map(|(h, s, v)| Color::from_hsv(h, s, v)),
+                _ => {
+                    return Err(fail(
+                        start..start + open,
+                        "expected a colour function such as rgb(), hsl() or hsv()",
+                    ))
+                }
+            };
+            return parsed.map_err(|e| e.within(raw, offset).into());
+        }
+
+        if let Some(kelvin) = trimmed.strip_suffix(['K', 'k']) {
+            if !kelvin.is_empty() && kelvin.chars().all(|c| c.is_ascii_digit()) {
+                return match kelvin.parse::<u32>() {
+                    Ok(kelvin) if TEMPERATURE_RANGE.contains(&kelvin) => {
+                        Ok(Color::from_temperature(kelvin))
+                    }
+                    _ => Err(fail(
+                        start..end - 1,
+                        "expected a colour temperature from 1000K to 40000K",
+                    )),
+                };
             }
+        }
+
+        if trimmed.len() == 6 && trimmed.chars().all(|c| c.is_ascii_hexdigit()) {
+            return parse_hex(trimmed).map_err(|e| e.within(raw, start).into());
+        }
+
+        if trimmed.contains(',') {
+            return parse_channels(trimmed).map_err(|e| e.within(raw, start).into());
+        }
+
+        if trimmed.starts_with(|c: char| c.is_ascii_alphabetic())
+            && trimmed.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            Err(fail(start..end, "unknown colour name"))
         } else {
-            let parts: Vec<&str> = s.split(',').collect();
-            Self {
-                red: parts[0]
-                    .trim()
-                    .parse::<u8>()
-                    .expect("Cant't read RED channel"),
-                green: parts[1]
-                    .trim()
-                    .parse::<u8>()
-                    .expect("Cant't read GREEN channel"),
-                blue: parts[2]
-                    .trim()
-                    .parse::<u8>()
-                    .expect("Cant't read BLUE channel"),
-            }
+            Err(fail(
+                start..end,
+                "expected a colour name, #rrggbb or rgb(r, g, b)",
+            ))
         }
     }
 }
 
+impl TryFrom<&str> for Color {
+    type Error = Error;
+
+    fn try_from(raw: &str) -> Result<Self, Self::Error> {
+        raw.parse()
+    }
+}
+
+/// Parses the 3 or 6 hexadecimal digits of a `#rgb` or `#rrggbb` colour
+fn parse_hex(digits: &str) -> Result<Color, ConversionError> {
+    if let Some((i, c)) = digits.char_indices().find(|(_, c)| !c.is_ascii_hexdigit()) {
+        return Err(ConversionError::new(
+            digits,
+            i..i + c.len_utf8(),
+            "expected a hexadecimal digit",
+        ));
+    }
+
+    let values: Vec<u8> = digits
+        .chars()
+        .filter_map(|c| c.to_digit(16))
+        .map(|d| d as u8)
+        .collect();
+    match values.as_slice() {
+        [r, g, b] => Ok(Color::from((r * 0x11, g * 0x11, b * 0x11))),
+        [r1, r2, g1, g2, b1, b2] => Ok(Color::from((r1 << 4 | r2, g1 << 4 | g2, b1 << 4 | b2))),
+        _ => Err(ConversionError::new(
+            digits,
+            0..digits.len(),
+            "expected 3 or 6 hexadecimal digits, as in #f80 or #ff8800",
+        )),
+    }
+}
+
+/// Parses 3 channels separated by commas, each from 0 to 255 or a percentage
+fn parse_channels(raw: &str) -> Result<Color, ConversionError> {
+    let parts: Vec<&str> = raw.split(',').collect();
+    if parts.len() != 3 {
+        return Err(ConversionError::new(
+            raw,
+            0..raw.len(),
+            "expected 3 channels, as in rgb(255, 128, 0)",
+        ));
+    }
+
+    let mut channels = [0x00; 3];
+    let mut offset = 0;
+    for (channel, part) in channels.iter_mut().zip(parts) {
+        let value = part.trim();
+        let start = offset + part.len() - part.trim_start().len();
+        *channel = parse_channel(value).ok_or_else(|| {
+            ConversionError::new(
+                raw,
+                start..start + value.len(),
+                "expected a number from 0 to 255 or a percentage",
+            )
+        })?;
+        offset += part.len() + 1;
+    }
+
+    Ok(Color::from((channels[0], channels[1], channels[2])))
+}
+
+fn parse_channel(raw: &str) -> Option<u8> {
+    match raw.strip_suffix('%') {
+        Some(percent) => parse_percent(percent).map(to_byte),
+        None => raw.parse::<u8>().ok(),
+    }
+}
+
+/// Parses the hue in degrees and the 2 percentages of `hsl()` and `hsv()`
+fn parse_hue(raw: &str) -> Result<(f64, f64, f64), ConversionError> {
+    let parts: Vec<&str> = raw.split(',').collect();
+    if parts.len() != 3 {
+        return Err(ConversionError::new(
+            raw,
+            0..raw.len(),
+            "expected a hue and 2 percentages, as in hsl(210, 50%, 60%)",
+        ));
+    }
+
+    let mut values = [0.0; 3];
+    let mut offset = 0;
+    for (i, part) in parts.iter().enumerate() {
+        let value = part.trim();
+        let start = offset + part.len() - part.trim_start().len();
+        let parsed = match i {
+            0 => value
+                .strip_suffix("deg")
+                .unwrap_or(value)
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|h| h.is_finite()),
+            _ => parse_percent(value.strip_suffix('%').unwrap_or(value)),
+        };
+        values[i] = parsed.ok_or_else(|| {
+            let hint = match i {
+                0 => "expected a hue in degrees",
+                _ => "expected a percentage from 0% to 100%",
+            };
+            ConversionError::new(raw, start..start + value.len(), hint)
+        })?;
+        offset += part.len() + 1;
+    }
+
+    Ok((values[0], values[1], values[2]))
+}
+
+/// Parses a percentage from 0 to 100, without its `%` sign, as a fraction
+fn parse_percent(raw: &str) -> Option<f64> {
+    match raw.trim().parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => Some(percent / 100.0),
+        _ => None,
+    }
+}
+
+/// A sequence of up to 14 colour stops played by the device over `duration`.
+/// Each stop has a position, in percent of the duration.
+/// Gradients with triggers are played once each time one of the trigger buttons is pressed,
+/// instead of repeating forever.
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct RGBGradient {
-    _duration: Duration,
+    /// `None` when not given, in which case a single colour is sent without a duration
+    duration: Option<Duration>,
     colors: Vec<Color>,
+    positions: Vec<u8>,
+    triggers: u8,
 }
 
-impl Default for RGBGradient {
-    fn default() -> Self {
-        Self {
-            _duration: DEFAULT_DURATION,
-            colors: vec![],
+/// Prints a gradient in the syntax accepted by `try_from`, or a single colour as is
+impl Display for RGBGradient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.colors.as_slice() {
+            [color] => write!(f, "{}", color)?,
+            _ => {
+                let stops: Vec<String> = self
+                    .stops()
+                    .map(|(color, position)| format!("{}@{}%", color, position))
+                    .collect();
+                write!(f, "{}", stops.join(","))?
+            }
+        }
+
+        if let Some(duration) = self.duration {
+            match duration.subsec_millis() {
+                0 => write!(f, ";duration={}s", duration.as_secs())?,
+                _ => write!(f, ";duration={}ms", duration.as_millis())?,
+            }
         }
+
+        if self.triggers != 0 {
+            write!(f, ";trigger={}", trigger_names(self.triggers))?;
+        }
+
+        Ok(())
     }
 }
 
+/// Parses a single colour, or a gradient such as `red@0,blue@40%,lime@100%;duration=2s`.
+/// Stop positions are percentages and can be left out, in which case stops are spread
+/// evenly between their neighbours. `trigger=click+right` plays the gradient on button presses.
 impl TryFrom<&str> for RGBGradient {
     type Error = Error;
 
     fn try_from(raw: &str) -> Result<Self, Self::Error> {
-        let hex_regex = regex::Regex::new(r"^[a-fA-F0-9]{6}$").unwrap();
+        let fail = |part: &str, hint: &str| {
+            let start = offset_of(raw, part);
+            Error::from(ConversionError::new(raw, start..start + part.len(), hint))
+        };
+
+        let mut parts = raw.split(';');
+        let stops = parts.next().unwrap_or("").trim();
+
+        // `r,g,b` colours can only be given alone since stops are separated by commas as well
         let rgb_regex = regex::Regex::new(r"^[0-9]{1,3},[0-9]{1,3},[0-9]{1,3}$").unwrap();
-        let color_literal_regex = regex::Regex::new(r"^[a-z]+$").unwrap();
-        if hex_regex.is_match(raw) || rgb_regex.is_match(raw) || color_literal_regex.is_match(raw) {
-            RGBGradient::new_with_colors(vec![Color::from(raw)])
+        let mut gradient = if rgb_regex.is_match(stops) {
+            RGBGradient::new_with_colors(vec![parse_color(raw, stops)?])?
         } else {
-            Err(Error::Conversion)
+            let mut colors = vec![];
+            let mut positions = vec![];
+            for stop in split_stops(stops) {
+                let (color, position) = match stop.split_once('@') {
+                    Some((color, position)) => match parse_position(position) {
+                        Some(parsed) => (color, Some(parsed)),
+                        None => return Err(fail(position, "expected a position from 0% to 100%")),
+                    },
+                    None => (stop, None),
+                };
+                colors.push(parse_color(raw, color)?);
+                positions.push(position);
+            }
+            RGBGradient::new_with_stops(colors.into_iter().zip(spread(positions)?).collect())?
+        };
+
+        for option in parts.map(str::trim).filter(|o| !o.is_empty()) {
+            match option.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+                Some(("duration", value)) => {
+                    gradient.duration = Some(
+                        crate::utils::parse_duration(value)
+                            .ok_or_else(|| fail(value, "expected a duration such as 500ms or 2s"))?,
+                    );
+                }
+                Some(("trigger", value)) => {
+                    for name in value.split('+').map(str::trim) {
+                        gradient.triggers |= parse_trigger(name).ok_or_else(|| {
+                            fail(
+                                name,
+                                "expected click, right, middle, back, forward or button1 to button8",
+                            )
+                        })?;
+                    }
+                }
+                _ => return Err(fail(option, "expected duration=… or trigger=…")),
+            }
+        }
+
+        Ok(gradient)
+    }
+}
+
+/// Parses the colour `part` of gradient `raw`, pointing errors into `raw`
+fn parse_color(raw: &str, part: &str) -> Result<Color, Error> {
+    part.parse().map_err(|e| match e {
+        Error::Conversion(Some(details)) => details.within(raw, offset_of(raw, part)).into(),
+        e => e,
+    })
+}
+
+/// Byte offset of `part` in `raw`, `part` being a slice of `raw`
+fn offset_of(raw: &str, part: &str) -> usize {
+    part.as_ptr() as usize - raw.as_ptr() as usize
+}
+
+/// Parses a trigger button name into its bit of the triggers field. `left` is the same as
+/// `click`, and buttons can also be given by number from `button1` to `button8`.
+fn parse_trigger(name: &str) -> Option<u8> {
+    let name = name.to_lowercase();
+    let bit = match name.as_str() {
+        "left" => Some(0),
+        _ => TRIGGER_NAMES.iter().position(|n| *n == name).or_else(|| {
+            name.strip_prefix("button")
+                .and_then(|n| n.parse::<usize>().ok())
+                .filter(|n| (1..=8).contains(n))
+                .map(|n| n - 1)
+        }),
+    };
+
+    bit.map(|bit| 1 << bit)
+}
+
+/// Names of the buttons set in `triggers`, joined by `+`
+fn trigger_names(triggers: u8) -> String {
+    let names: Vec<&str> = TRIGGER_NAMES
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| triggers & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect();
+
+    names.join("+")
+}
+
+/// Splits gradient stops on the commas found outside of parentheses
+fn split_stops(raw: &str) -> Vec<&str> {
+    let mut stops = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in raw.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                stops.push(&raw[start..i]);
+                start = i + 1;
+            }
+            _ => {}
         }
     }
+    stops.push(&raw[start..]);
+
+    stops
+}
+
+/// Parses a stop position, in percent with or without the `%` sign
+fn parse_position(raw: &str) -> Option<u8> {
+    let raw = raw.trim();
+    match raw.strip_suffix('%').unwrap_or(raw).trim().parse::<u8>() {
+        Ok(position) if position <= 100 => Some(position),
+        _ => None,
+    }
+}
+
+/// Fills in missing stop positions: the first stop defaults to 0%, the last one to 100%
+/// and the others are spread evenly between their neighbours
+fn spread(positions: Vec<Option<u8>>) -> Result<Vec<u8>, Error> {
+    let count = positions.len();
+    let mut known: Vec<(usize, u8)> = positions
+        .iter()
+        .enumerate()
+        .filter_map(|(i, p)| p.map(|p| (i, p)))
+        .collect();
+    if positions.first() == Some(&None) {
+        known.insert(0, (0, 0));
+    }
+    if count > 1 && positions.last() == Some(&None) {
+        known.push((count - 1, 100));
+    }
+
+    let mut spread = vec![0; count];
+    for pair in known.windows(2) {
+        let ((from, start), (to, end)) = (pair[0], pair[1]);
+        if end < start {
+            return Err(Error::Generic("Gradient stops must be in increasing order"));
+        }
+        for (i, position) in spread.iter_mut().enumerate().take(to + 1).skip(from) {
+            *position = start + ((end - start) as usize * (i - from) / (to - from)) as u8;
+        }
+    }
+    if let [(i, position)] = known.as_slice() {
+        spread[*i] = *position;
+    }
+
+    Ok(spread)
 }
 
 impl From<Vec<Color>> for RGBGradient {
@@ -159,36 +810,105 @@ impl From<Vec<Color>> for RGBGradient {
 }
 
 impl RGBGradient {
+    /// A gradient of `colors` spread evenly over its duration
     pub fn new_with_colors(colors: Vec<Color>) -> Result<Self, Error> {
-        if colors.len() > 14 {
+        let positions = spread(vec![None; colors.len()])?;
+        RGBGradient::new_with_stops(colors.into_iter().zip(positions).collect())
+    }
+
+    /// A gradient of colours at the given positions, in percent of its duration
+    pub fn new_with_stops(stops: Vec<(Color, u8)>) -> Result<Self, Error> {
+        if stops.len() > 14 {
             Err(Error::Generic(
                 "A maximum of 14 color stops can be defined in a gradient",
             ))
+        } else if stops.is_empty() {
+            Err(Error::Conversion(None))
+        } else if stops.windows(2).any(|pair| pair[1].1 < pair[0].1) || stops.iter().any(|s| s.1 > 100) {
+            Err(Error::Generic("Gradient stops must be in increasing order, up to 100%"))
         } else {
+            let (colors, positions) = stops.into_iter().unzip();
             Ok(Self {
-                _duration: DEFAULT_DURATION,
+                duration: None,
                 colors,
+                positions,
+                triggers: 0,
             })
         }
     }
 
-    pub(crate) fn iter_colors(&self) -> Iter<Color> {
+    pub(crate) fn iter_colors(&self) -> Iter<'_, Color> {
         self.colors.iter()
     }
 
+    /// Colour stops and their position, in percent of the duration
+    pub fn stops(&self) -> impl Iterator<Item = (&Color, u8)> {
+        self.colors.iter().zip(self.positions.iter().copied())
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration.unwrap_or(DEFAULT_DURATION)
+    }
+
+    /// The buttons triggering the gradient, as a bit field with the left button first
+    pub fn triggers(&self) -> u8 {
+        self.triggers
+    }
+
     pub fn is_gradient(&self) -> bool {
         self.colors.len() > 1
     }
 
-    pub(crate) fn process(&self, settings: RGBGradientSettings) -> ProcessedRGBGradient {
+    /// Encodes the gradient as laid out by `settings`, with its colours corrected.
+    /// Fails if the gradient is empty or the device can not play it over its duration.
+    #[cfg(any(test, feature = "headsets", feature = "mice"))]
+    pub(crate) fn process(
+        &self,
+        settings: RGBGradientSettings,
+    ) -> Result<ProcessedRGBGradient<'_>, Error> {
+        let first = self.colors.first().ok_or(Error::Conversion(None))?;
+
         // Generate header
 
-        let mut header: Vec<u8> = vec![];
-        for _ in 0..settings.header_length {
-            header.push(0x00);
+        let mut header: Vec<u8> = vec![0x00; settings.header_length as usize];
+
+        // The duration is stored in milliseconds, little endian
+        let duration_length = settings.duration_length.min(8) as u32;
+        let capacity = match duration_length {
+            0 => u64::MAX,
+            length => u64::MAX >> (64 - 8 * length),
+        };
+        let min = settings.duration_min as u64;
+        let max = (settings.duration_max as u64).min(capacity);
+        // A single colour given without a duration is sent without one, as it does not change
+        let duration = match self.duration {
+            None if !self.is_gradient() => 0,
+            _ => {
+                let duration = self.duration().as_millis() as u64;
+                if duration < min || duration > max {
+                    return Err(Error::OutOfRange(format!(
+                        "The gradient duration must be between {}ms and {}ms",
+                        min, max
+                    )));
+                }
+                duration
+            }
+        };
+        let start = settings.duration_offset as usize;
+        header[start..start + duration_length as usize]
+            .copy_from_slice(&duration.to_le_bytes()[..duration_length as usize]);
+
+        let unsupported = self.triggers & !settings.triggers_mask;
+        if unsupported != 0 {
+            return Err(Error::OutOfRange(format!(
+                "This device can not be triggered by {}",
+                trigger_names(unsupported)
+            )));
         }
+        header[settings.triggers_offset as usize] = self.triggers;
 
-        if self.is_gradient() {
+        // Triggered gradients play once per press
+        if self.is_gradient() && self.triggers == 0 {
             header[settings.repeat_offset as usize] = 0x01;
         }
 
@@ -202,49 +922,100 @@ impl RGBGradient {
 
         let mut body: Vec<u8> = vec![];
 
-        body.push(self.colors[0].red);
-        body.push(self.colors[0].green);
-        body.push(self.colors[0].blue);
+        let first: [u8; 3] = settings.correction.apply(first).into();
+        body.extend_from_slice(&first);
 
+        // Each stop is followed by its distance to the previous one, 255 being the whole duration
         let mut last_real_pos = 0;
-        let mut color_index = 0;
-        self.iter_colors().for_each(|color| {
-            let real_pos = color_index * 255 / 100;
-            let color_bytes: [u8; 3] = color.clone().into();
+        self.stops().for_each(|(color, position)| {
+            let real_pos = (position as u32 * 255 + 50) / 100;
+            let color_bytes: [u8; 3] = settings.correction.apply(color).into();
             let mut color_bytes: Vec<u8> = color_bytes.into();
             body.append(&mut color_bytes);
             body.push((real_pos - last_real_pos) as u8);
             last_real_pos = real_pos;
-            color_index += 1;
         });
 
         header.append(&mut body);
 
-        ProcessedRGBGradient {
+        Ok(ProcessedRGBGradient {
             _gradient: self,
             bytes: header,
+        })
+    }
+
+    /// Decodes a gradient encoded by `process` with the same `settings`, such as
+    /// the payload of a feature report read back from a device
+    #[cfg(any(test, feature = "headsets", feature = "mice"))]
+    pub(crate) fn decode(bytes: &[u8], settings: RGBGradientSettings) -> Result<Self, Error> {
+        let header_length = settings.header_length as usize;
+        let color_count = *bytes
+            .get(settings.color_count_offset as usize)
+            .ok_or(Error::Conversion(None))? as usize;
+        if color_count == 0 {
+            return Err(Error::Conversion(None));
+        }
+
+        // The body starts with the first color, followed by 4 bytes per color stop
+        let body_start = header_length + 3;
+        let stops = bytes
+            .get(body_start..body_start + color_count * 4)
+            .ok_or(Error::Conversion(None))?;
+        let mut real_pos = 0;
+        let stops = stops
+            .chunks(4)
+            .map(|stop| {
+                real_pos += stop[3] as u32;
+                let color = settings.correction.revert(&Color::from(&stop[..3]));
+                (color, ((real_pos * 100 + 127) / 255) as u8)
+            })
+            .collect();
+
+        let start = settings.duration_offset as usize;
+        let length = settings.duration_length.min(8) as usize;
+        let duration = bytes
+            .get(start..start + length)
+            .ok_or(Error::Conversion(None))?
+            .iter()
+            .rev()
+            .fold(0u64, |duration, byte| duration << 8 | *byte as u64);
+
+        let mut gradient = RGBGradient::new_with_stops(stops)?;
+        gradient.triggers = *bytes
+            .get(settings.triggers_offset as usize)
+            .ok_or(Error::Conversion(None))?;
+        // No duration is reported for colours set without one
+        if duration > 0 {
+            gradient.duration = Some(Duration::from_millis(duration));
         }
+        Ok(gradient)
     }
 }
 
 impl From<Color> for RGBGradient {
     fn from(source: Color) -> Self {
         Self {
-            _duration: DEFAULT_DURATION,
+            duration: None,
             colors: vec![source],
+            positions: vec![0],
+            triggers: 0,
         }
     }
 }
 
-#[allow(dead_code)]
+#[cfg(any(test, feature = "headsets", feature = "mice"))]
 pub(crate) struct RGBGradientSettings<'a> {
     pub(crate) header_length: u16,
     pub(crate) led_id_offsets: &'a [u8],
     pub(crate) duration_offset: u8,
     pub(crate) duration_length: u8,
+    pub(crate) duration_min: u16,
+    pub(crate) duration_max: u16,
     pub(crate) repeat_offset: u8,
     pub(crate) triggers_offset: u8,
+    pub(crate) triggers_mask: u8,
     pub(crate) color_count_offset: u16,
+    pub(crate) correction: ColorCorrection,
 }
 
 pub struct ProcessedRGBGradient<'a> {
@@ -260,13 +1031,142 @@ impl ProcessedRGBGradient<'_> {
 
 #[cfg(test)]
 mod tests {
-    use super::Color;
+    use std::{convert::TryFrom, time::Duration};
+
+    use super::{Color, ColorCorrection, RGBGradient, RGBGradientSettings};
+    use crate::steelseries_core::Error;
+
     #[test]
     fn conversion() {
-        let rgb = "112,152,205";
-        let hex = "#7098CD";
-        assert_eq!(Color::from(hex), Color::from(rgb));
-        let tuple: (u8, u8, u8) = Color::from(hex).into();
+        let rgb: Color = "112,152,205".parse().unwrap();
+        let hex: Color = "#7098CD".parse().unwrap();
+        assert_eq!(hex, rgb);
+        let tuple: (u8, u8, u8) = hex.into();
         assert_eq!(tuple, (112, 152, 205));
+
+        assert_eq!("#f80".parse::<Color>().unwrap(), Color::from((0xff, 0x88, 0x00)));
+        assert_eq!("RGB(255, 136, 0)".parse::<Color>().unwrap(), Color::from((0xff, 0x88, 0x00)));
+        assert_eq!("Red".parse::<Color>().unwrap(), Color::from((0xff, 0x00, 0x00)));
+        assert_eq!("rgb(0, 100%, 0)".parse::<Color>().unwrap(), Color::from((0x00, 0xff, 0x00)));
+
+        match "rgb(12, 300, 0)".parse::<Color>() {
+            Err(Error::Conversion(Some(e))) => assert_eq!(e.span, 8..11),
+            other => panic!("unexpected {:?}", other),
+        }
+        match RGBGradient::try_from("red,#12x456") {
+            Err(Error::Conversion(Some(e))) => assert_eq!(e.span, 7..8),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!("rgb(1, 2".parse::<Color>().is_err());
+        assert!("#12345".parse::<Color>().is_err());
+        assert!("blurple".parse::<Color>().is_err());
+
+        let parse = |raw: &str| -> (u8, u8, u8) { raw.parse::<Color>().unwrap().into() };
+        assert_eq!(parse("hsl(210, 50%, 60%)"), (0x66, 0x99, 0xcc));
+        assert_eq!(parse("hsv(120deg, 100%, 50%)"), (0x00, 0x80, 0x00));
+        assert_eq!(parse("6600K"), (0xff, 0xff, 0xff));
+        assert_eq!(parse("2700k"), (0xff, 0xa7, 0x57));
+        assert!("hsl(210, 50%, 160%)".parse::<Color>().is_err());
+        assert!("100K".parse::<Color>().is_err());
+    }
+
+    #[test]
+    fn gradient_decodes_what_it_encodes() {
+        let settings = || RGBGradientSettings {
+            header_length: 26,
+            led_id_offsets: &[0],
+            duration_offset: 1,
+            duration_length: 2,
+            duration_min: 0,
+            duration_max: u16::MAX,
+            repeat_offset: 17,
+            triggers_offset: 21,
+            triggers_mask: 0x1f,
+            color_count_offset: 25,
+            correction: ColorCorrection::default(),
+        };
+        let gradient = RGBGradient::try_from("red,blue;duration=2s").unwrap();
+        let processed = gradient.process(settings()).unwrap();
+
+        assert_eq!(
+            RGBGradient::decode(processed.as_slice(), settings()).unwrap(),
+            gradient
+        );
+
+        let too_slow = RGBGradientSettings {
+            duration_max: 1000,
+            ..settings()
+        };
+        assert!(gradient.process(too_slow).is_err());
+
+        // A single colour has no duration unless given one
+        let red = RGBGradient::from(Color::from((0xff, 0x00, 0x00)));
+        let processed = red.process(settings()).unwrap();
+        assert_eq!(processed.as_slice()[1..3], [0x00, 0x00]);
+        assert_eq!(RGBGradient::decode(processed.as_slice(), settings()).unwrap(), red);
+
+        assert!(matches!(
+            RGBGradient::default().process(settings()),
+            Err(Error::Conversion(None))
+        ));
+    }
+
+    #[test]
+    fn color_correction() {
+        let orange = Color::from((0xff, 0x80, 0x00));
+        let dimmed = ColorCorrection {
+            brightness: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(dimmed.apply(&orange), Color::from((0x80, 0x40, 0x00)));
+
+        let correction = ColorCorrection {
+            gamma: [2.2; 3],
+            balance: [1.0, 0.8, 0.6],
+            ..Default::default()
+        };
+        let corrected = correction.apply(&orange);
+        assert_eq!(corrected, Color::from((0xff, 0x2d, 0x00)));
+        assert_eq!(correction.revert(&corrected), orange);
+        assert_eq!(
+            correction.apply(&Color::from((0xff, 0xff, 0xff))),
+            Color::from((0xff, 0xcc, 0x99))
+        );
+
+        let calibrated = ColorCorrection {
+            matrix: [[0.9, 0.1, 0.0], [0.0, 0.8, 0.0], [0.0, 0.0, 0.5]],
+            ..Default::default()
+        };
+        let corrected = calibrated.apply(&orange);
+        assert_eq!(corrected, Color::from((0xf2, 0x66, 0x00)));
+        // Up to rounding
+        assert_eq!(calibrated.revert(&corrected), Color::from((0xff, 0x7f, 0x00)));
+    }
+
+    #[test]
+    fn gradient_syntax() {
+        let red = Color::from((0xff, 0x00, 0x00));
+        let blue = Color::from((0x00, 0x00, 0xff));
+        let lime = Color::from((0x00, 0xff, 0x00));
+
+        let gradient = RGBGradient::try_from("red@0,blue@40%,lime@100%;duration=2s").unwrap();
+        let stops: Vec<(&Color, u8)> = gradient.stops().collect();
+        assert_eq!(stops, vec![(&red, 0), (&blue, 40), (&lime, 100)]);
+        assert_eq!(gradient.duration(), Duration::from_secs(2));
+        assert_eq!(gradient.to_string(), "#ff0000@0%,#0000ff@40%,#00ff00@100%;duration=2s");
+        assert_eq!(RGBGradient::try_from(gradient.to_string().as_str()).unwrap(), gradient);
+
+        let spread = RGBGradient::try_from("red,blue,lime").unwrap();
+        assert_eq!(spread.stops().map(|(_, p)| p).collect::<Vec<u8>>(), vec![0, 50, 100]);
+
+        assert!(RGBGradient::try_from("112,152,205").is_ok());
+        assert!(RGBGradient::try_from("red@50,blue@10").is_err());
+        assert!(RGBGradient::try_from("red@101%").is_err());
+        assert!(RGBGradient::try_from("red;speed=2").is_err());
+
+        let reactive = RGBGradient::try_from("red;trigger=click+button5").unwrap();
+        assert_eq!(reactive.triggers(), 0x11);
+        assert_eq!(reactive.to_string(), "#ff0000;trigger=click+forward");
+        assert!(RGBGradient::try_from("red;trigger=wheel").is_err());
     }
 }
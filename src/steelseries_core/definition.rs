@@ -0,0 +1,226 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use super::{
+    DeviceProfileValue, DeviceProperty, Error, FromCode, HidDeviceInfo, Result,
+};
+
+/// Directory holding device definitions installed system wide
+pub const SYSTEM_DEFINITIONS_DIR: &str = "/usr/share/steelcli/devices";
+
+/// Describes a device: how to recognize it, what it can do and the values its driver
+/// needs to build commands for it. Definitions are read from TOML or YAML files.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeviceDefinition {
+    /// Extended device name
+    pub name: String,
+
+    /// A short name to identify the device
+    pub slug: String,
+
+    /// The driver implementing the device protocol, eg. `sensei_ten`
+    pub driver: String,
+
+    pub vendor_id: u16,
+    pub product_id: u16,
+
+    /// The HID interface accepting the device commands. Devices expose several
+    /// interfaces and only one of them takes feature reports: it can be selected by
+    /// interface number, by usage page and usage, or both.
+    #[serde(default)]
+    pub interface: Option<i32>,
+    #[serde(default)]
+    pub usage_page: Option<u16>,
+    #[serde(default)]
+    pub usage: Option<u16>,
+
+    /// Property codes supported by the device, eg. `lc`
+    pub capabilities: Vec<String>,
+
+    /// Driver specific values, such as command bytes and gradient layout
+    #[serde(default)]
+    pub profile: HashMap<String, DeviceProfileValue>,
+}
+
+impl DeviceDefinition {
+    pub fn from_toml(source: &str) -> Result<Self> {
+        toml::from_str::<Self>(source)
+            .map_err(|e| Error::Definition(e.to_string()))?
+            .validate()
+    }
+
+    pub fn from_yaml(source: &str) -> Result<Self> {
+        serde_yaml::from_str::<Self>(source)
+            .map_err(|e| Error::Definition(e.to_string()))?
+            .validate()
+    }
+
+    /// Checks if `info` is the interface of a device described by this definition.
+    /// Values the platform does not report are not checked.
+    pub fn accepts(&self, info: &HidDeviceInfo) -> bool {
+        let interface = match self.interface {
+            Some(interface) => info.interface_number == -1 || info.interface_number == interface,
+            None => true,
+        };
+        let usage_page = match self.usage_page {
+            Some(usage_page) => info.usage_page == 0 || info.usage_page == usage_page,
+            None => true,
+        };
+        let usage = match self.usage {
+            Some(usage) => info.usage_page == 0 || info.usage == usage,
+            None => true,
+        };
+
+        info.matches(self.vendor_id, self.product_id) && interface && usage_page && usage
+    }
+
+    /// Reads a definition file, picking the format from its extension
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml(source.as_str()),
+            Some("yml") | Some("yaml") => Self::from_yaml(source.as_str()),
+            _ => Err(Error::Definition(format!(
+                "{} is not a TOML or YAML file",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Reads every definition file found in `dir`, sorted by file name.
+    /// A missing directory yields no definitions.
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Vec<(PathBuf, Result<Self>)> {
+        let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| {
+                    matches!(
+                        path.extension().and_then(|e| e.to_str()),
+                        Some("toml") | Some("yml") | Some("yaml")
+                    )
+                })
+                .collect(),
+            Err(_) => vec![],
+        };
+        paths.sort();
+
+        paths
+            .into_iter()
+            .map(|path| {
+                let definition = Self::from_file(&path);
+                (path, definition)
+            })
+            .collect()
+    }
+
+    /// The properties listed in `capabilities`, all known once the definition is validated
+    pub fn properties(&self) -> Vec<DeviceProperty> {
+        self.capabilities
+            .iter()
+            .filter_map(|code| DeviceProperty::from_code(code).ok())
+            .collect()
+    }
+
+    fn validate(self) -> Result<Self> {
+        for code in self.capabilities.iter() {
+            if DeviceProperty::from_code(code).is_err() {
+                return Err(Error::Definition(format!(
+                    "Unknown capability {} for device {}",
+                    code, self.slug
+                )));
+            }
+        }
+
+        self.validate_gradient_header()?;
+        Ok(self)
+    }
+
+    /// Checks that the `rgbgradh_*` offsets of the profile lie within the gradient header,
+    /// whose bytes they are written to
+    fn validate_gradient_header(&self) -> Result<()> {
+        let header_length = match self.profile.get("rgbgradh_header_length") {
+            Some(value) => value.as_hex().ok_or_else(|| {
+                Error::Definition(format!(
+                    "Invalid rgbgradh_header_length for device {}",
+                    self.slug
+                ))
+            })? as usize,
+            None => return Ok(()),
+        };
+
+        let out_of_header = |key: &str| {
+            Error::Definition(format!(
+                "{} of device {} lies beyond the {} bytes of the gradient header",
+                key, self.slug, header_length
+            ))
+        };
+        let offset = |key: &str| self.profile.get(key).and_then(|v| v.as_hex());
+
+        for key in [
+            "rgbgradh_repeat_offset",
+            "rgbgradh_triggers_offset",
+            "rgbgradh_color_count_offset",
+        ] {
+            if offset(key).is_some_and(|offset| offset as usize >= header_length) {
+                return Err(out_of_header(key));
+            }
+        }
+
+        let led_id_offsets = self
+            .profile
+            .get("rgbgradh_led_id_offsets")
+            .and_then(|v| v.as_byte_list())
+            .unwrap_or_default();
+        if led_id_offsets
+            .iter()
+            .any(|offset| *offset as usize >= header_length)
+        {
+            return Err(out_of_header("rgbgradh_led_id_offsets"));
+        }
+
+        // The duration is at most 8 bytes long, see `RGBGradient::process`
+        let duration_end = offset("rgbgradh_duration_offset").unwrap_or(0) as usize
+            + offset("rgbgradh_duration_length").unwrap_or(0).min(8) as usize;
+        if duration_end > header_length {
+            return Err(out_of_header("rgbgradh_duration_length"));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeviceDefinition;
+    use crate::steelseries_core::Error;
+
+    const SENSEI_TEN: &str = include_str!("../devices/definitions/senseiten.toml");
+
+    #[test]
+    fn gradient_offsets_within_header() {
+        assert!(DeviceDefinition::from_toml(SENSEI_TEN).is_ok());
+
+        for (from, to) in [
+            ("count_offset = 25", "count_offset = 26"),
+            ("id_offsets = [0]", "id_offsets = [0, 40]"),
+            ("duration_offset = 1", "duration_offset = 25"),
+            ("header_length = 26", "header_length = 4"),
+        ] {
+            let definition = SENSEI_TEN.replace(from, to);
+            assert!(
+                matches!(
+                    DeviceDefinition::from_toml(definition.as_str()),
+                    Err(Error::Definition(_))
+                ),
+                "{} was accepted",
+                to
+            );
+        }
+    }
+}
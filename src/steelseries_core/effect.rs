@@ -0,0 +1,227 @@
+//# Lighting effects rendered in software, one frame at a time, and sent to a device
+//# through its regular colour properties
+
+use std::{
+    f64::consts::PI,
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{
+    Color, DeviceProperty, Error, PropertyKind, PropertyValue, Result, SteelseriesDevice,
+    TaskOptions,
+};
+
+/// How often a new frame is rendered while an effect plays
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Names of the available effects, as accepted by `Effect::new`
+pub const EFFECT_NAMES: &[&str] = &["breathe", "cycle", "strobe", "rainbow", "fade"];
+
+/// A lighting effect, described as the colour to show at any time since it started
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    /// Fades `color` in and out once per period
+    Breathe { color: Color, period: Duration },
+    /// Blends from each of `colors` to the next, spending a period on each
+    Cycle { colors: Vec<Color>, period: Duration },
+    /// Flashes `color` once per period
+    Strobe { color: Color, period: Duration },
+    /// Goes around the colour wheel once per period
+    Rainbow { period: Duration },
+    /// Fades `color` to black over a period, then stops
+    Fade { color: Color, period: Duration },
+}
+
+impl Effect {
+    /// Builds the effect called `name`. Effects showing a single colour use the first of `colors`.
+    pub fn new(name: &str, colors: Vec<Color>, period: Duration) -> Result<Self> {
+        if period.is_zero() {
+            return Err(Error::Conversion(None));
+        }
+
+        let color = colors.first().cloned();
+        match (name, color) {
+            ("breathe", Some(color)) => Ok(Effect::Breathe { color, period }),
+            ("cycle", Some(_)) => Ok(Effect::Cycle { colors, period }),
+            ("strobe", Some(color)) => Ok(Effect::Strobe { color, period }),
+            ("rainbow", _) => Ok(Effect::Rainbow { period }),
+            ("fade", Some(color)) => Ok(Effect::Fade { color, period }),
+            (name, None) if EFFECT_NAMES.contains(&name) => {
+                Err(Error::Generic("This effect requires a colour"))
+            }
+            _ => Err(Error::Unsupported),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Effect::Breathe { .. } => "breathe",
+            Effect::Cycle { .. } => "cycle",
+            Effect::Strobe { .. } => "strobe",
+            Effect::Rainbow { .. } => "rainbow",
+            Effect::Fade { .. } => "fade",
+        }
+    }
+
+    /// How long the effect lasts, `None` for effects repeating forever
+    pub fn duration(&self) -> Option<Duration> {
+        match self {
+            Effect::Fade { period, .. } => Some(*period),
+            _ => None,
+        }
+    }
+
+    /// The colour to show `elapsed` after the effect started
+    pub fn frame(&self, elapsed: Duration) -> Color {
+        let periods = |period: &Duration| elapsed.as_secs_f64() / period.as_secs_f64();
+        match self {
+            Effect::Breathe { color, period } => {
+                color.scaled((1.0 - (2.0 * PI * periods(period)).cos()) / 2.0)
+            }
+            Effect::Cycle { colors, period } => {
+                let periods = periods(period);
+                let from = periods as usize % colors.len();
+                let to = (from + 1) % colors.len();
+                colors[from].mix(&colors[to], periods.fract())
+            }
+            Effect::Strobe { color, period } => match periods(period).fract() < 0.25 {
+                true => color.clone(),
+                false => Color::from((0x00, 0x00, 0x00)),
+            },
+            Effect::Rainbow { period } => {
+                Color::from_hsv(periods(period).fract() * 360.0, 1.0, 1.0)
+            }
+            Effect::Fade { color, period } => color.scaled(1.0 - periods(period)),
+        }
+    }
+}
+
+/// An effect playing on a colour property of a device
+pub struct Animation {
+    effect: Effect,
+    property: DeviceProperty,
+    started: Instant,
+    last: Option<Color>,
+    finished: bool,
+}
+
+impl Animation {
+    /// Starts `effect` on `property`, or on the first colour property of `device` when `None`
+    pub fn new(
+        effect: Effect,
+        device: &dyn SteelseriesDevice,
+        property: Option<DeviceProperty>,
+    ) -> Result<Self> {
+        let property = match property {
+            Some(property) => device.supports_capability(property).copied(),
+            None => device
+                .enumerate_capabilities()
+                .find(|c| matches!(c.value_kind(), PropertyKind::Color | PropertyKind::Gradient))
+                .copied(),
+        }
+        .ok_or(Error::InvalidCapability)?;
+
+        Ok(Self {
+            effect,
+            property,
+            started: Instant::now(),
+            last: None,
+            finished: false,
+        })
+    }
+
+    pub fn effect(&self) -> &Effect {
+        &self.effect
+    }
+
+    pub fn property(&self) -> DeviceProperty {
+        self.property
+    }
+
+    /// Whether the last frame rendered was the last frame of the effect
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Sends the current frame to `device`, unless it is the frame sent last
+    pub fn render(&mut self, device: &dyn SteelseriesDevice, options: &TaskOptions) -> Result<()> {
+        let elapsed = self.started.elapsed();
+        let frame = match self.effect.duration() {
+            Some(duration) => {
+                self.finished = elapsed >= duration;
+                self.effect.frame(elapsed.min(duration))
+            }
+            None => self.effect.frame(elapsed),
+        };
+        if self.last.as_ref() == Some(&frame) {
+            return Ok(());
+        }
+
+        // Frames are never saved to the device memory
+        let options = TaskOptions {
+            save: false,
+            ..*options
+        };
+        device.change_property(self.property, PropertyValue::Color(frame.clone()), &options)?;
+        self.last = Some(frame);
+
+        Ok(())
+    }
+
+    /// Renders frames on `device` until the effect is over or `until` elapsed,
+    /// forever for repeating effects
+    pub fn play(
+        &mut self,
+        device: &dyn SteelseriesDevice,
+        until: Option<Duration>,
+        options: &TaskOptions,
+    ) -> Result<()> {
+        loop {
+            self.render(device, options)?;
+            let expired = until.is_some_and(|until| self.started.elapsed() >= until);
+            if self.is_finished() || expired {
+                return Ok(());
+            }
+            thread::sleep(FRAME_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Effect;
+    use crate::steelseries_core::Color;
+
+    #[test]
+    fn frames() {
+        let red = Color::from((0xff, 0x00, 0x00));
+        let period = Duration::from_secs(2);
+
+        let breathe = Effect::new("breathe", vec![red.clone()], period).unwrap();
+        assert_eq!(breathe.frame(Duration::ZERO), Color::from((0x00, 0x00, 0x00)));
+        assert_eq!(breathe.frame(Duration::from_secs(1)), red);
+
+        let cycle = Effect::new(
+            "cycle",
+            vec![red.clone(), Color::from((0x00, 0x00, 0xff))],
+            period,
+        )
+        .unwrap();
+        assert_eq!(cycle.frame(Duration::from_secs(1)), Color::from((0x80, 0x00, 0x80)));
+        assert_eq!(cycle.frame(Duration::from_secs(4)), red);
+
+        let rainbow = Effect::new("rainbow", vec![], period).unwrap();
+        assert_eq!(rainbow.frame(Duration::ZERO), red);
+        assert_eq!(
+            rainbow.frame(Duration::from_millis(1333)),
+            Color::from((0x00, 0x00, 0xff))
+        );
+
+        let fade = Effect::new("fade", vec![red], period).unwrap();
+        assert_eq!(fade.duration(), Some(period));
+        assert_eq!(fade.frame(period), Color::from((0x00, 0x00, 0x00)));
+    }
+}
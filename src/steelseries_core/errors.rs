@@ -1,20 +1,143 @@
+use std::{fmt::Display, ops::Range};
+
 /// All error types used internally
 #[derive(Debug)]
 pub enum Error {
     Generic(&'static str),
     NoDevice,
+    /// A device was selected by something that is not a device specification
+    InvalidSpec(String),
     OpenDevice,
     Privileges,
-    Conversion,
+    /// A value could not be parsed, with details when they are known
+    Conversion(Option<ConversionError>),
     InvalidCapability,
+    Unsupported,
+    #[cfg(feature = "hidapi-backend")]
     Usb(hidapi::HidError),
-    UsbComm
+    UsbComm,
+    Timeout,
+    Io(std::io::Error),
+    Replay(String),
+    Definition(String),
+    Profile(String),
+    Palette(String),
+    Calibration(String),
+    OutOfRange(String),
+    MissingProfileValue(&'static str),
 }
 
+#[cfg(feature = "hidapi-backend")]
 impl From<hidapi::HidError> for Error {
     fn from(original: hidapi::HidError) -> Self {
         Error::Usb(original)
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(original: std::io::Error) -> Self {
+        Error::Io(original)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Generic(message) => write!(f, "{}", message),
+            Error::NoDevice => write!(f, "No such device connected"),
+            Error::InvalidSpec(spec) => write!(
+                f,
+                "Invalid device spec {}, expected a slug, slug#N, slug@serial, \
+                 vendor_id:product_id or a HID path",
+                spec
+            ),
+            Error::OpenDevice => write!(f, "Cannot open the device"),
+            Error::Privileges => write!(f, "Cannot escalate privileges"),
+            Error::Conversion(Some(details)) => write!(f, "{}", details),
+            Error::Conversion(None) => write!(f, "Invalid value"),
+            Error::InvalidCapability => write!(f, "Property not supported by the device"),
+            Error::Unsupported => write!(f, "Operation not supported"),
+            #[cfg(feature = "hidapi-backend")]
+            Error::Usb(e) => write!(f, "USB error: {}", e),
+            Error::UsbComm => write!(f, "Communication with the device failed"),
+            Error::Timeout => write!(f, "The device did not answer in time"),
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Replay(message) => write!(f, "Cannot replay the capture: {}", message),
+            Error::Definition(message) => write!(f, "Invalid device definition: {}", message),
+            Error::Profile(message) => write!(f, "{}", message),
+            Error::Palette(message) => write!(f, "Invalid palette {}", message),
+            Error::Calibration(message) => write!(f, "Invalid calibration: {}", message),
+            Error::OutOfRange(message) => write!(f, "{}", message),
+            Error::MissingProfileValue(key) => {
+                write!(f, "The device definition has no {} value", key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Where and why parsing a value failed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionError {
+    /// The whole value being parsed
+    pub input: String,
+    /// Byte range of the offending part of `input`
+    pub span: Range<usize>,
+    /// What was expected instead
+    pub hint: String,
+}
+
+impl ConversionError {
+    pub fn new(input: &str, span: Range<usize>, hint: &str) -> Self {
+        Self {
+            input: input.to_string(),
+            span,
+            hint: hint.to_string(),
+        }
+    }
+
+    /// Moves the span `by` bytes, to point into `input` which contains the value parsed
+    pub fn within(self, input: &str, by: usize) -> Self {
+        Self {
+            input: input.to_string(),
+            span: self.span.start + by..self.span.end + by,
+            hint: self.hint,
+        }
+    }
+}
+
+/// Prints the value with the offending part underlined:
+///
+/// ```text
+/// Invalid value, expected a hexadecimal digit
+///     #12345g
+///           ^
+/// ```
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let start = self.input[..self.span.start.min(self.input.len())].chars().count();
+        let width = self
+            .input
+            .get(self.span.clone())
+            .map(|s| s.chars().count())
+            .unwrap_or(0)
+            .max(1);
+        write!(
+            f,
+            "Invalid value, {}\n    {}\n    {}{}",
+            self.hint,
+            self.input,
+            " ".repeat(start),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl From<ConversionError> for Error {
+    fn from(details: ConversionError) -> Self {
+        Error::Conversion(Some(details))
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
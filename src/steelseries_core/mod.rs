@@ -1,17 +1,39 @@
 //# This modules contains all the basic structs and traits to interact with Steelseries devices
 //#
 
+mod backend;
+mod calibration;
+#[cfg(feature = "capture")]
+mod capture;
 mod color;
-mod log;
+mod definition;
+mod effect;
+mod palette;
+mod persistent;
+mod profile_store;
+mod property_value;
 mod steelseries_device;
+mod transaction;
 mod errors;
 mod side;
+mod transport;
 
 pub mod support;
+pub use backend::*;
+pub use calibration::*;
+#[cfg(feature = "capture")]
+pub use capture::*;
 pub use color::*;
-pub use log::*;
+pub use definition::*;
+pub use effect::*;
+pub use palette::*;
+pub use persistent::*;
+pub use profile_store::*;
+pub use property_value::*;
 pub use steelseries_device::*;
+pub use transaction::*;
 pub use errors::*;
 pub use side::*;
+pub use transport::*;
 
 pub const STEELSERIES_VENDOR_ID: u16 = 0x1038;
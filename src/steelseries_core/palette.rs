@@ -0,0 +1,242 @@
+//# Colour names defined by the user, on top of the CSS named colours, as loaded from
+//# `~/.config/steelcli/palette.toml` or from a GIMP palette
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use super::{Color, Error, Result, NAMED_COLORS};
+
+lazy_static! {
+    static ref USER_PALETTE: RwLock<Palette> = RwLock::new(Palette::new());
+}
+
+/// A set of named colours, either from a TOML file mapping names to colours in any form
+/// accepted on the command line:
+///
+/// ```toml
+/// brand-teal = "#00a4a6"
+/// desk = "2700K"
+/// ```
+///
+/// or from a GIMP `.gpl` palette, whose colour names are lowercased and have their spaces
+/// replaced by dashes, `Brand Teal` becoming `brand-teal`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Palette {
+    colors: BTreeMap<String, Color>,
+}
+
+impl Palette {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds colour `name`, replacing any colour with the same name
+    pub fn insert(&mut self, name: &str, color: Color) -> Result<()> {
+        let name = normalize_name(name);
+        let mut chars = name.chars();
+        let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(Error::Palette(format!(
+                "{} is not a valid colour name, expected letters, digits and dashes",
+                name
+            )));
+        }
+
+        self.colors.insert(name, color);
+        Ok(())
+    }
+
+    /// Adds the colours of `other`, replacing those with the same names
+    pub fn extend(&mut self, other: Palette) {
+        self.colors.extend(other.colors);
+    }
+
+    /// The colour called `name`, in any case
+    pub fn get(&self, name: &str) -> Option<&Color> {
+        self.colors.get(normalize_name(name).as_str())
+    }
+
+    /// Iterates over colours, sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Color)> {
+        self.colors.iter().map(|(name, color)| (name.as_str(), color))
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self> {
+        let values: BTreeMap<String, String> =
+            toml::from_str(source).map_err(|e| Error::Palette(e.to_string()))?;
+
+        let mut palette = Self::new();
+        for (name, value) in values {
+            let color = value
+                .parse()
+                .map_err(|e| Error::Palette(format!("{}: {}", name, e)))?;
+            palette.insert(&name, color)?;
+        }
+
+        Ok(palette)
+    }
+
+    pub fn from_gpl(source: &str) -> Result<Self> {
+        let mut lines = source.lines().enumerate();
+        if lines.next().map(|(_, l)| l.trim()) != Some("GIMP Palette") {
+            return Err(Error::Palette("expected a GIMP palette".to_string()));
+        }
+
+        let mut palette = Self::new();
+        for (i, line) in lines {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || is_gpl_header(line) {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let channels: Vec<u8> = fields
+                .by_ref()
+                .take(3)
+                .filter_map(|c| c.parse::<u8>().ok())
+                .collect();
+            let name = fields.collect::<Vec<&str>>().join(" ");
+            let color = match channels.as_slice() {
+                [r, g, b] => Color::from((*r, *g, *b)),
+                _ => {
+                    return Err(Error::Palette(format!(
+                        "line {}: expected red, green and blue from 0 to 255",
+                        i + 1
+                    )))
+                }
+            };
+
+            // GIMP names colours added without a name "Untitled"
+            if name.is_empty() || name == "Untitled" {
+                continue;
+            }
+            palette
+                .insert(&name, color)
+                .map_err(|e| prefixed(e, format!("line {}", i + 1)))?;
+        }
+
+        Ok(palette)
+    }
+
+    /// Loads a palette file, a GIMP palette if it has the `.gpl` extension and TOML otherwise
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .map_err(|e| Error::Palette(format!("{}: {}", path.display(), e)))?;
+        let palette = match path.extension().and_then(|e| e.to_str()) {
+            Some("gpl") => Self::from_gpl(source.as_str()),
+            _ => Self::from_toml(source.as_str()),
+        };
+
+        palette.map_err(|e| prefixed(e, path.display().to_string()))
+    }
+
+    /// The palettes in the user configuration directory, `palette.toml` and `palette.gpl`,
+    /// the former taking precedence. Missing files are skipped, invalid ones too with a
+    /// warning, so that the CSS named colours remain usable.
+    pub fn user() -> Self {
+        let mut palette = Self::new();
+        for path in Self::user_paths() {
+            if path.is_file() {
+                log::debug!("Loading palette {}", path.display());
+                match Self::load(&path) {
+                    Ok(mut loaded) => {
+                        loaded.extend(palette);
+                        palette = loaded;
+                    }
+                    Err(e) => log::warn!("Skipping palette: {}", e),
+                }
+            }
+        }
+
+        palette
+    }
+
+    /// Where `user` looks for palettes, eg. `~/.config/steelcli/palette.toml`
+    pub fn user_paths() -> Vec<PathBuf> {
+        crate::utils::config_dir()
+            .map(|dir| vec![dir.join("palette.toml"), dir.join("palette.gpl")])
+            .unwrap_or_default()
+    }
+}
+
+/// Makes the colours of `palette` available by name wherever a colour is parsed
+pub fn set_user_palette(palette: Palette) {
+    if let Ok(mut user) = USER_PALETTE.write() {
+        *user = palette;
+    }
+}
+
+/// The colours set with `set_user_palette`
+pub fn user_palette() -> Palette {
+    USER_PALETTE
+        .read()
+        .map(|palette| palette.clone())
+        .unwrap_or_default()
+}
+
+/// The colour called `name` in the user palette or else among the CSS named colours
+pub fn lookup_color(name: &str) -> Option<Color> {
+    let user = USER_PALETTE
+        .read()
+        .ok()
+        .and_then(|palette| palette.get(name).cloned());
+    user.or_else(|| NAMED_COLORS.get(normalize_name(name).as_str()).cloned())
+}
+
+fn normalize_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join("-")
+        .to_lowercase()
+}
+
+/// Tells where palette error `e` comes from
+fn prefixed(e: Error, location: String) -> Error {
+    match e {
+        Error::Palette(message) => Error::Palette(format!("{}: {}", location, message)),
+        e => e,
+    }
+}
+
+fn is_gpl_header(line: &str) -> bool {
+    ["Name:", "Columns:"].iter().any(|h| line.starts_with(h))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Palette;
+    use crate::steelseries_core::Color;
+
+    #[test]
+    fn palette_files() {
+        let toml = Palette::from_toml("brand-teal = \"#00a4a6\"\nDesk = \"rgb(255, 167, 87)\"\n")
+            .unwrap();
+        assert_eq!(toml.get("Brand-Teal"), Some(&Color::from((0x00, 0xa4, 0xa6))));
+        assert_eq!(toml.get("desk"), Some(&Color::from((0xff, 0xa7, 0x57))));
+        assert!(Palette::from_toml("teal = \"#00a4a\"").is_err());
+        assert!(Palette::from_toml("\"#teal\" = \"red\"").is_err());
+
+        let gpl = Palette::from_gpl(
+            "GIMP Palette\nName: Brand\nColumns: 2\n#\n  0 164 166\tBrand Teal\n255 255 255\tUntitled\n",
+        )
+        .unwrap();
+        assert_eq!(gpl.len(), 1);
+        assert_eq!(gpl.get("brand-teal"), Some(&Color::from((0x00, 0xa4, 0xa6))));
+        assert!(Palette::from_gpl("GIMP Palette\n0 300 0 Too bright\n").is_err());
+        assert!(Palette::from_gpl("brand-teal = \"#00a4a6\"").is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use super::{Backend, HidDeviceInfo, Result, Transport};
+
+/// A backend keeping every transport it opens, so that later opens of the same
+/// device reuse it instead of going through the inner backend again.
+/// Meant for long running processes such as the daemon, and for batches of changes.
+pub struct PersistentBackend {
+    inner: Rc<dyn Backend>,
+    open: RefCell<HashMap<String, Rc<dyn Transport>>>,
+}
+
+impl PersistentBackend {
+    pub fn new(inner: Rc<dyn Backend>) -> Self {
+        Self {
+            inner,
+            open: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl Backend for PersistentBackend {
+    fn device_list(&self) -> Vec<HidDeviceInfo> {
+        self.inner.device_list()
+    }
+
+    fn open(&self, info: &HidDeviceInfo) -> Result<Box<dyn Transport>> {
+        let mut open = self.open.borrow_mut();
+        let transport = match open.get(&info.path) {
+            Some(transport) => Rc::clone(transport),
+            None => {
+                let transport: Rc<dyn Transport> = Rc::from(self.inner.open(info)?);
+                open.insert(info.path.clone(), Rc::clone(&transport));
+                transport
+            }
+        };
+
+        Ok(Box::new(SharedTransport { inner: transport }))
+    }
+
+    /// Refreshes the inner backend and closes the transports of devices that are gone
+    fn refresh(&self) -> Result<()> {
+        self.inner.refresh()?;
+        let connected = self.inner.device_list();
+        self.open
+            .borrow_mut()
+            .retain(|path, _| connected.iter().any(|d| &d.path == path));
+        Ok(())
+    }
+}
+
+/// A transport shared between several device handles
+struct SharedTransport {
+    inner: Rc<dyn Transport>,
+}
+
+impl Transport for SharedTransport {
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn send_feature_report(&self, buf: &[u8]) -> Result<()> {
+        self.inner.send_feature_report(buf)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        self.inner.get_feature_report(buf)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> Result<usize> {
+        self.inner.read_timeout(buf, timeout)
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Error, Result};
+
+/// A named set of property values for one or more devices, as stored in a profile file:
+///
+/// ```toml
+/// [senseiten]
+/// lc = "red"
+/// ```
+///
+/// Tables are named after a device, using any form accepted on the command line.
+/// Not to be confused with the driver specific values of a device definition.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Profile {
+    devices: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the raw value of property `code` for `device`
+    pub fn set(&mut self, device: &str, code: &str, value: &str) {
+        self.devices
+            .entry(device.to_string())
+            .or_default()
+            .insert(code.to_string(), value.to_string());
+    }
+
+    /// Iterates over devices and their property values, sorted by device and property code
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &BTreeMap<String, String>)> {
+        self.devices.iter().map(|(d, values)| (d.as_str(), values))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.values().all(|values| values.is_empty())
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self> {
+        toml::from_str(source).map_err(|e| Error::Profile(e.to_string()))
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).map_err(|e| Error::Profile(e.to_string()))
+    }
+}
+
+/// A directory of profiles, one `<name>.toml` file each
+pub struct ProfileStore {
+    dir: PathBuf,
+}
+
+impl ProfileStore {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The store in the user configuration directory, eg. `~/.config/steelcli/profiles`
+    pub fn user() -> Result<Self> {
+        crate::utils::config_dir()
+            .map(|dir| Self::new(dir.join("profiles")))
+            .ok_or(Error::Generic(
+                "Cannot find the user configuration directory",
+            ))
+    }
+
+    /// Names of the stored profiles, sorted
+    pub fn list(&self) -> Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().and_then(|e| e.to_str()) == Some("toml"))
+            .filter_map(|path| path.file_stem().and_then(|s| s.to_str()).map(String::from))
+            .collect();
+        names.sort();
+
+        Ok(names)
+    }
+
+    pub fn load(&self, name: &str) -> Result<Profile> {
+        let path = self.path(name)?;
+        match fs::read_to_string(&path) {
+            Ok(source) => Profile::from_toml(source.as_str()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(Error::Profile(format!("No profile named {}", name)))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Stores `profile` as `name`, replacing any profile with the same name
+    pub fn save(&self, name: &str, profile: &Profile) -> Result<()> {
+        let path = self.path(name)?;
+        fs::create_dir_all(&self.dir)?;
+        fs::write(path, profile.to_toml()?)?;
+        Ok(())
+    }
+
+    pub fn delete(&self, name: &str) -> Result<()> {
+        match fs::remove_file(self.path(name)?) {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(Error::Profile(format!("No profile named {}", name)))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The file holding profile `name`. Names are restricted to letters, digits, `-` and `_`
+    /// so that they can not point outside the store.
+    fn path(&self, name: &str) -> Result<PathBuf> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if valid {
+            Ok(self.dir.join(format!("{}.toml", name)))
+        } else {
+            Err(Error::Profile(format!("Invalid profile name {}", name)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Profile, ProfileStore};
+    use crate::steelseries_core::Error;
+
+    #[test]
+    fn save_load_delete() {
+        let dir = std::env::temp_dir().join(format!("steelcli-profiles-{}", std::process::id()));
+        let store = ProfileStore::new(&dir);
+        assert_eq!(store.list().unwrap(), Vec::<String>::new());
+
+        let mut profile = Profile::new();
+        profile.set("senseiten", "lc", "red");
+        profile.set("arctis5@0123", "hc", "#00a4a6");
+        store.save("evening", &profile).unwrap();
+        store.save("day_2", &Profile::new()).unwrap();
+        assert_eq!(store.list().unwrap(), vec!["day_2", "evening"]);
+        assert_eq!(store.load("evening").unwrap(), profile);
+
+        store.delete("evening").unwrap();
+        assert!(matches!(store.load("evening"), Err(Error::Profile(_))));
+        assert!(matches!(store.delete("evening"), Err(Error::Profile(_))));
+
+        for name in ["", "../evening", "night/owl", "my profile"] {
+            assert!(matches!(store.load(name), Err(Error::Profile(_))));
+            assert!(matches!(store.save(name, &profile), Err(Error::Profile(_))));
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
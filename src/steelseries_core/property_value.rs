@@ -0,0 +1,80 @@
+use std::{convert::TryFrom, fmt::Display, time::Duration};
+
+use super::{Color, ConversionError, Error, RGBGradient, Result};
+
+/// A property value, already parsed and validated against the property it is meant for
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    Color(Color),
+    Gradient(RGBGradient),
+    Integer(i64),
+    Bool(bool),
+    Choice(String),
+    Duration(Duration),
+}
+
+impl Display for PropertyValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PropertyValue::Color(color) => write!(f, "{}", color),
+            PropertyValue::Gradient(gradient) => write!(f, "{}", gradient),
+            PropertyValue::Integer(value) => write!(f, "{}", value),
+            PropertyValue::Bool(value) => write!(f, "{}", if *value { "on" } else { "off" }),
+            PropertyValue::Choice(value) => write!(f, "{}", value),
+            PropertyValue::Duration(value) => write!(f, "{}ms", value.as_millis()),
+        }
+    }
+}
+
+/// Describes what kind of value a property accepts
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyKind {
+    Color,
+    Gradient,
+    Integer { min: i64, max: i64 },
+    Bool,
+    Choice(&'static [&'static str]),
+    Duration,
+}
+
+impl PropertyKind {
+    /// Parses a raw value given on the command line into a `PropertyValue` of this kind
+    pub fn parse(&self, raw: &str) -> Result<PropertyValue> {
+        let raw = raw.trim();
+        match self {
+            PropertyKind::Color => {
+                // Gradient options such as a duration or triggers are not accepted either
+                let gradient = RGBGradient::try_from(raw)?;
+                match gradient.iter_colors().as_slice() {
+                    [color] if RGBGradient::from(color.clone()) == gradient => {
+                        Ok(PropertyValue::Color(color.clone()))
+                    }
+                    _ => Err(ConversionError::new(
+                        raw,
+                        0..raw.len(),
+                        "expected a single colour, this property does not take gradients",
+                    )
+                    .into()),
+                }
+            }
+            PropertyKind::Gradient => Ok(PropertyValue::Gradient(RGBGradient::try_from(raw)?)),
+            PropertyKind::Integer { min, max } => match raw.parse::<i64>() {
+                Ok(value) if value >= *min && value <= *max => Ok(PropertyValue::Integer(value)),
+                _ => Err(Error::Conversion(None)),
+            },
+            PropertyKind::Bool => match raw.to_lowercase().as_str() {
+                "on" | "true" | "yes" | "1" => Ok(PropertyValue::Bool(true)),
+                "off" | "false" | "no" | "0" => Ok(PropertyValue::Bool(false)),
+                _ => Err(Error::Conversion(None)),
+            },
+            PropertyKind::Choice(choices) => choices
+                .iter()
+                .find(|choice| choice.eq_ignore_ascii_case(raw))
+                .map(|choice| PropertyValue::Choice(choice.to_string()))
+                .ok_or(Error::Conversion(None)),
+            PropertyKind::Duration => crate::utils::parse_duration(raw)
+                .map(PropertyValue::Duration)
+                .ok_or(Error::Conversion(None)),
+        }
+    }
+}
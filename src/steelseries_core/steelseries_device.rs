@@ -1,17 +1,40 @@
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::time::{Duration, Instant};
 
-use hidapi::HidError;
+use serde::Deserialize;
 
-use super::{Error, Result};
+use super::{Backend, Error, HidDeviceInfo, PropertyKind, PropertyValue, Result, Transport};
 
+/// How long to wait for a device to acknowledge a command when no timeout is given
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy)]
 pub struct TaskOptions {
     pub save: bool,
     pub dry: bool,
+
+    /// The maximum time to wait for each response from the device
+    pub timeout: Duration,
+
+    /// Brightness of the colours sent, in percent, instead of the one of the device definition
+    pub brightness: Option<u8>,
+}
+
+impl Default for TaskOptions {
+    fn default() -> Self {
+        Self {
+            save: false,
+            dry: false,
+            timeout: DEFAULT_TIMEOUT,
+            brightness: None,
+        }
+    }
 }
 
-pub trait FromCode {
-    fn from_code(code: &str) -> Self;
+pub trait FromCode: Sized {
+    /// Fails with `Error::InvalidCapability` for unknown codes
+    fn from_code(code: &str) -> Result<Self>;
 }
 
 pub trait ToCode {
@@ -23,8 +46,8 @@ pub trait ToDescription {
 }
 
 /// Represents a device property that can be queried and/or manipulated
-#[allow(dead_code)]
-#[derive(Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum DeviceProperty {
     LeftHeadphoneLedColor,
     RightHeadphoneLedColor,
@@ -33,14 +56,28 @@ pub enum DeviceProperty {
     LedColor,
 }
 
+impl DeviceProperty {
+    /// The kind of value this property accepts
+    pub fn value_kind(&self) -> PropertyKind {
+        match self {
+            DeviceProperty::LedColor => PropertyKind::Gradient,
+            DeviceProperty::LeftHeadphoneLedColor
+            | DeviceProperty::RightHeadphoneLedColor
+            | DeviceProperty::HeadphonesColor
+            | DeviceProperty::WheelLedColor => PropertyKind::Color,
+        }
+    }
+}
+
 impl FromCode for DeviceProperty {
-    fn from_code(code: &str) -> Self {
+    fn from_code(code: &str) -> Result<Self> {
         match code {
-            "lhc" => DeviceProperty::LeftHeadphoneLedColor,
-            "rhc" => DeviceProperty::RightHeadphoneLedColor,
-            "hc" => DeviceProperty::HeadphonesColor,
-            "lc" => DeviceProperty::LedColor,
-            _ => DeviceProperty::LedColor,
+            "lhc" => Ok(DeviceProperty::LeftHeadphoneLedColor),
+            "rhc" => Ok(DeviceProperty::RightHeadphoneLedColor),
+            "hc" => Ok(DeviceProperty::HeadphonesColor),
+            "lc" => Ok(DeviceProperty::LedColor),
+            "wc" => Ok(DeviceProperty::WheelLedColor),
+            _ => Err(Error::InvalidCapability),
         }
     }
 }
@@ -75,8 +112,10 @@ impl Display for DeviceProperty {
     }
 }
 
-impl<'a> From<&'a str> for DeviceProperty {
-    fn from(code: &'a str) -> Self {
+impl<'a> TryFrom<&'a str> for DeviceProperty {
+    type Error = Error;
+
+    fn try_from(code: &'a str) -> Result<Self> {
         DeviceProperty::from_code(code)
     }
 }
@@ -84,95 +123,110 @@ impl<'a> From<&'a str> for DeviceProperty {
 /// An handle over a single device. Tipically obtained by calling `open` on a `SteelSeriesDevice` implementor.
 /// Use to read and write data to the device.
 pub struct SteelseriesDeviceHandle {
-    _info: hidapi::DeviceInfo,
-    pub(crate) handle: hidapi::HidDevice,
+    _info: HidDeviceInfo,
+    transport: Box<dyn Transport>,
 }
 
 impl SteelseriesDeviceHandle {
-    pub fn new(device_info: hidapi::DeviceInfo) -> Result<Self> {
-        let api = &crate::HIDAPI;
-        if let Ok(device_handle) = device_info.open_device(&api) {
-            Ok(Self {
-                _info: device_info,
-                handle: device_handle,
-            })
-        } else {
-            Err(Error::UsbComm)
+    pub fn new(device_info: HidDeviceInfo, transport: Box<dyn Transport>) -> Self {
+        Self {
+            _info: device_info,
+            transport,
         }
     }
 
-    #[allow(dead_code)]
     pub fn write(&self, buf: &[u8]) -> Result<usize> {
-        match self.handle.write(buf) {
-            Ok(written) => Ok(written),
-            Err(some_error) => Err(Error::Usb(some_error)),
-        }
+        self.transport.write(buf)
     }
 
     pub fn send_feature_report(&self, buf: &[u8]) -> Result<()> {
-        match self.handle.send_feature_report(buf) {
-            Ok(_) => Ok(()),
-            Err(some_error) => Err(Error::Usb(some_error)),
-        }
+        self.transport.send_feature_report(buf)
     }
-}
 
-impl<T> From<T> for SteelseriesDeviceHandle
-where
-    T: SteelseriesDevice,
-{
-    fn from(source: T) -> Self {
-        let api = &crate::HIDAPI;
-        let device_info = api
-            .device_list()
-            .find(|d| source.matches(d.vendor_id(), d.product_id()))
-            .unwrap()
-            .clone();
-        device_info.open().unwrap()
+    pub fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        self.transport.get_feature_report(buf)
+    }
+
+    pub fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> Result<usize> {
+        self.transport.read_timeout(buf, timeout)
+    }
+
+    /// Waits for an input report starting with `ack`, ignoring any other report.
+    /// Fails with `Error::Timeout` if none arrives within `timeout`.
+    pub fn wait_ack(&self, ack: &[u8], timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        let mut buf = [0x00; 64];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::Timeout);
+            }
+
+            let millis = remaining.as_millis().clamp(1, i32::MAX as u128) as i32;
+            let read = self.read_timeout(&mut buf, millis)?;
+            if read > 0 && buf[..read].starts_with(ack) {
+                return Ok(());
+            }
+        }
     }
 }
 
 /// Each device has one or more profile specific values that need to be stored
 /// for later usage. These are the types that are storable.
-#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
 pub enum DeviceProfileValue {
-    Str(&'static str),
     Byte(u8),
     Hex(u16),
-    ByteList(&'static [u8]),
+    Str(String),
+    ByteList(Vec<u8>),
+    Float(f64),
+    FloatList(Vec<f64>),
 }
 
 impl DeviceProfileValue {
-    #[allow(dead_code)]
-    pub(crate) fn as_str(&self) -> Option<&'static str> {
-        if let DeviceProfileValue::Str(value) = self {
-            Some(value)
-        } else {
-            None
+    #[cfg(any(feature = "headsets", feature = "mice"))]
+    pub(crate) fn as_byte(&self) -> Option<u8> {
+        match self {
+            DeviceProfileValue::Byte(value) => Some(*value),
+            DeviceProfileValue::Hex(value) => u8::try_from(*value).ok(),
+            _ => None,
         }
     }
 
-    pub(crate) fn as_byte(&self) -> Option<&u8> {
-        if let DeviceProfileValue::Byte(value) = self {
-            Some(value)
-        } else {
-            None
+    pub(crate) fn as_hex(&self) -> Option<u16> {
+        match self {
+            DeviceProfileValue::Byte(value) => Some(*value as u16),
+            DeviceProfileValue::Hex(value) => Some(*value),
+            _ => None,
         }
     }
 
-    pub(crate) fn as_hex(&self) -> Option<&u16> {
-        if let DeviceProfileValue::Hex(value) = self {
+    pub(crate) fn as_byte_list(&self) -> Option<&[u8]> {
+        if let DeviceProfileValue::ByteList(value) = self {
             Some(value)
         } else {
             None
         }
     }
 
-    pub(crate) fn as_byte_list(&self) -> Option<&[u8]> {
-        if let DeviceProfileValue::ByteList(value) = self {
-            Some(value)
-        } else {
-            None
+    #[cfg(any(feature = "headsets", feature = "mice"))]
+    pub(crate) fn as_float(&self) -> Option<f64> {
+        match self {
+            DeviceProfileValue::Byte(value) => Some(*value as f64),
+            DeviceProfileValue::Hex(value) => Some(*value as f64),
+            DeviceProfileValue::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The values of a list, whether written as integers or not
+    #[cfg(any(feature = "headsets", feature = "mice"))]
+    pub(crate) fn as_float_list(&self) -> Option<Vec<f64>> {
+        match self {
+            DeviceProfileValue::ByteList(values) => Some(values.iter().map(|v| *v as f64).collect()),
+            DeviceProfileValue::FloatList(values) => Some(values.clone()),
+            _ => None,
         }
     }
 }
@@ -180,7 +234,7 @@ impl DeviceProfileValue {
 /// A trait implemented by structures designed to represent a single device
 pub trait SteelseriesDevice {
     /// Enumerates this devices capabilities and configurable properties
-    fn enumerate_capabilities(&self) -> std::slice::Iter<DeviceProperty>;
+    fn enumerate_capabilities(&self) -> std::slice::Iter<'_, DeviceProperty>;
 
     /// Extended device name
     fn get_name(&self) -> &str;
@@ -192,10 +246,22 @@ pub trait SteelseriesDevice {
     fn change_property(
         &self,
         property: DeviceProperty,
-        value: &str,
+        value: PropertyValue,
         options: &TaskOptions,
     ) -> Result<()>;
 
+    /// Reads the current value of a single property back from the device
+    fn read_property(&self, property: DeviceProperty) -> Result<PropertyValue> {
+        let _ = property;
+        Err(Error::Unsupported)
+    }
+
+    /// Saves the current settings to the device internal memory
+    fn save(&self, options: &TaskOptions) -> Result<()> {
+        let _ = options;
+        Err(Error::Unsupported)
+    }
+
     /// The device vendor id
     fn get_vendor_id(&self) -> u16;
 
@@ -203,7 +269,7 @@ pub trait SteelseriesDevice {
     fn get_product_id(&self) -> u16;
 
     /// Returns a map of profile values for this device
-    fn get_profile(&self) -> Option<&HashMap<&str, DeviceProfileValue>>;
+    fn get_profile(&self) -> Option<&HashMap<String, DeviceProfileValue>>;
 
     /// If `capability` is contained in the `enumerate_capabilities` iterators returns `Some(capability)`,
     /// `None` otherwise
@@ -211,16 +277,29 @@ pub trait SteelseriesDevice {
         self.enumerate_capabilities().find(|c| **c == capability)
     }
 
+    /// The backend used to reach this device
+    fn get_backend(&self) -> &dyn Backend;
+
+    /// The connected device this device is bound to, if any.
+    /// Unbound devices open the first connected device matching their ids.
+    fn get_instance(&self) -> Option<&HidDeviceInfo> {
+        None
+    }
+
     /// Opens the device and returns an handle to it
     fn open(&self) -> Result<SteelseriesDeviceHandle> {
-        let api = &crate::HIDAPI;
-        let dev = api.device_list().find(|d| {
-            d.vendor_id() == self.get_vendor_id() && d.product_id() == self.get_product_id()
-        });
+        let backend = self.get_backend();
+        let dev = match self.get_instance() {
+            Some(instance) => Some(instance.clone()),
+            None => backend.device_list().into_iter().find(|d| self.accepts(d)),
+        };
 
         match dev {
-            Some(connected_device) => SteelseriesDeviceHandle::new(connected_device.clone()),
-            None => Err(Error::Usb(HidError::HidApiErrorEmpty)),
+            Some(connected_device) => {
+                let transport = backend.open(&connected_device)?;
+                Ok(SteelseriesDeviceHandle::new(connected_device, transport))
+            }
+            None => Err(Error::NoDevice),
         }
     }
 
@@ -232,47 +311,18 @@ pub trait SteelseriesDevice {
         }
     }
 
+    /// Checks if `info` is the HID interface this device should be opened on.
+    /// By default any interface with the device ids is accepted.
+    fn accepts(&self, info: &HidDeviceInfo) -> bool {
+        info.matches(self.get_vendor_id(), self.get_product_id())
+    }
+
     /// Checks if the device mathches a given `vendor_id:product_id` combination
     fn matches(&self, vendor_id: u16, product_id: u16) -> bool {
         self.get_vendor_id() == vendor_id && self.get_product_id() == product_id
     }
 }
 
-impl SteelseriesDevice for hidapi::DeviceInfo {
-    fn enumerate_capabilities(&self) -> std::slice::Iter<DeviceProperty> {
-        panic!("Cannot enumerate capabilities on raw device info. You shouldn't have called this.")
-    }
-
-    fn get_name(&self) -> &str {
-        self.product_string().unwrap_or("???")
-    }
-
-    fn get_slug(&self) -> &str {
-        self.get_name()
-    }
-
-    fn change_property(
-        &self,
-        _property: DeviceProperty,
-        _value: &str,
-        _options: &TaskOptions,
-    ) -> Result<()> {
-        Ok(())
-    }
-
-    fn get_vendor_id(&self) -> u16 {
-        self.vendor_id()
-    }
-
-    fn get_product_id(&self) -> u16 {
-        self.product_id()
-    }
-
-    fn get_profile(&self) -> Option<&HashMap<&str, DeviceProfileValue>> {
-        None
-    }
-}
-
 impl<T> ToDescription for T
 where
     T: SteelseriesDevice,
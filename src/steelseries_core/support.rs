@@ -1,30 +1,117 @@
-use super::SteelseriesDevice;
-use crate::HIDAPI;
-use lazy_static::__Deref;
+use std::{path::PathBuf, rc::Rc};
+
+#[cfg(feature = "hidapi-backend")]
+use super::HidApiBackend;
+use super::{
+    Backend, DeviceDefinition, Error, HidDeviceInfo, Result, SteelseriesDevice, ToCode,
+    SYSTEM_DEFINITIONS_DIR,
+};
 
 pub struct DevicePool {
-    api: &'static HIDAPI,
-    pub(crate) devices: Vec<Box<dyn SteelseriesDevice>>,
+    backend: Rc<dyn Backend>,
+    definitions: Vec<DeviceDefinition>,
+    devices: Vec<Box<dyn SteelseriesDevice>>,
+}
+
+#[cfg(feature = "hidapi-backend")]
+impl Default for DevicePool {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DevicePool {
+    /// Creates a pool of supported devices reached through hidapi
+    #[cfg(feature = "hidapi-backend")]
     pub fn new() -> Self {
-        let mut devices = vec![];
+        Self::with_backend(Rc::new(HidApiBackend::new()))
+    }
 
-        let arctis_five: Box<dyn SteelseriesDevice> =
-            Box::new(crate::devices::ArctisFiveHeadphones::new());
-        devices.push(arctis_five);
+    /// Creates a pool of supported devices reached through `backend`
+    pub fn with_backend(backend: Rc<dyn Backend>) -> Self {
+        let mut pool = Self::with_definitions(backend, crate::devices::builtin_definitions());
+        for dir in definition_dirs() {
+            pool.load_definitions(dir);
+        }
 
-        let sensei_ten: Box<dyn SteelseriesDevice> =
-            Box::new(crate::devices::SenseiTenMouse::new());
-        devices.push(sensei_ten);
+        pool
+    }
 
-        Self {
-            api: &HIDAPI,
-            devices,
+    /// Creates a pool of the devices in `definitions` reached through `backend`, leaving
+    /// out the definitions installed on the system or by the user
+    pub fn with_definitions(backend: Rc<dyn Backend>, definitions: Vec<DeviceDefinition>) -> Self {
+        let mut pool = Self {
+            backend,
+            definitions: vec![],
+            devices: vec![],
+        };
+
+        for definition in definitions {
+            if let Err(e) = pool.add_definition(definition) {
+                log::warn!("Skipping device definition: {}", e);
+            }
+        }
+
+        pool
+    }
+
+    /// Adds every device defined in `dir` to the pool. A definition replaces any
+    /// previously known device with the same slug.
+    pub fn load_definitions(&mut self, dir: PathBuf) {
+        for (path, definition) in DeviceDefinition::load_dir(&dir) {
+            match definition {
+                Ok(definition) => {
+                    let slug = definition.slug.clone();
+                    match self.add_definition(definition) {
+                        Ok(()) => {
+                            log::debug!("Loaded device definition {} from {}", slug, path.display())
+                        }
+                        Err(e) => {
+                            log::warn!("Skipping device definition {}: {}", path.display(), e)
+                        }
+                    }
+                }
+                Err(e) => {
+                    log::warn!("Skipping device definition {}: {:?}", path.display(), e)
+                }
+            };
         }
     }
 
+    /// Adds the device described by `definition`, unless its driver does not exist or
+    /// cannot change every property the definition lists
+    fn add_definition(&mut self, definition: DeviceDefinition) -> Result<()> {
+        let unknown_driver = || {
+            Error::Definition(format!(
+                "Unknown driver {} for device {}",
+                definition.driver, definition.slug
+            ))
+        };
+        let handled =
+            crate::devices::driver_properties(&definition.driver).ok_or_else(unknown_driver)?;
+        if let Some(property) = definition
+            .properties()
+            .into_iter()
+            .find(|p| !handled.contains(p))
+        {
+            return Err(Error::Definition(format!(
+                "Driver {} cannot change {} of device {}",
+                definition.driver,
+                property.to_code(),
+                definition.slug
+            )));
+        }
+
+        let device =
+            crate::devices::from_definition(definition.clone(), Rc::clone(&self.backend), None)
+                .ok_or_else(unknown_driver)?;
+        self.definitions.retain(|d| d.slug != definition.slug);
+        self.devices.retain(|d| d.get_slug() != definition.slug);
+        self.definitions.push(definition);
+        self.devices.push(device);
+        Ok(())
+    }
+
     pub fn find_one(&self, vendor_id: u16, product_id: u16) -> Option<&dyn SteelseriesDevice> {
         let mut ret: Option<&dyn SteelseriesDevice> = None;
         self.devices.iter().for_each(|f| {
@@ -37,51 +124,369 @@ impl DevicePool {
     }
 
     pub fn find_by_slug(&self, slug: &str) -> Option<&dyn SteelseriesDevice> {
-        let mut ret: Option<&dyn SteelseriesDevice> = None;
-        self.devices.iter().for_each(|f| {
-            if f.get_slug() == slug {
-                ret = Some(&**f);
-            }
-        });
+        self.devices
+            .iter()
+            .find(|f| f.get_slug() == slug)
+            .map(|f| &**f)
+    }
 
-        ret
+    /// Finds a device from a specification given on the command line. Accepted forms are
+    /// a HID path, `slug#N` for the Nth connected device of a kind, `slug@serial`,
+    /// `vendor_id:product_id` and a plain slug.
+    pub fn find(&self, spec: &str) -> Option<&dyn SteelseriesDevice> {
+        if let Some(device) = self
+            .devices
+            .iter()
+            .find(|d| d.get_instance().map(|i| i.path.as_str()) == Some(spec))
+        {
+            return Some(&**device);
+        }
+
+        if let Some((slug, index)) = spec.split_once('#') {
+            let index = index.parse::<usize>().ok()?.checked_sub(1)?;
+            return self
+                .devices
+                .iter()
+                .filter(|d| d.get_slug() == slug)
+                .nth(index)
+                .map(|d| &**d);
+        }
+
+        if let Some((slug, serial)) = spec.split_once('@') {
+            return self
+                .devices
+                .iter()
+                .find(|d| {
+                    d.get_slug() == slug
+                        && d.get_instance().and_then(|i| i.serial_number.as_deref())
+                            == Some(serial)
+                })
+                .map(|d| &**d);
+        }
+
+        if spec.contains(':') {
+            let (vendor_id, product_id) = crate::utils::parse_device_id(spec)?;
+            return self.find_one(vendor_id, product_id);
+        }
+
+        self.find_by_slug(spec)
+    }
+
+    /// Finds a device like `find`, failing with `Error::InvalidSpec` when `spec` is in none
+    /// of the accepted forms and with `Error::NoDevice` when no device matches it
+    pub fn lookup(&self, spec: &str) -> Result<&dyn SteelseriesDevice> {
+        if let Some(device) = self.find(spec) {
+            return Ok(device);
+        }
+
+        let valid = if let Some((_, index)) = spec.split_once('#') {
+            index.parse::<usize>().is_ok_and(|index| index > 0)
+        } else if spec.contains('@') {
+            true
+        } else if spec.contains(':') {
+            crate::utils::parse_device_id(spec).is_some()
+        } else {
+            !spec.is_empty()
+        };
+
+        if valid {
+            Err(Error::NoDevice)
+        } else {
+            Err(Error::InvalidSpec(spec.to_string()))
+        }
     }
 
-    #[allow(dead_code)]
-    pub fn first(&self) -> Option<&dyn SteelseriesDevice> {
-        Some(self.devices[0].deref())
+    /// Lists the devices in the pool along with the `slug#N` name that selects each of them
+    pub fn instances(&self) -> Vec<(String, &dyn SteelseriesDevice)> {
+        let mut instances: Vec<(String, &dyn SteelseriesDevice)> = vec![];
+        for device in self.devices.iter() {
+            let ordinal = instances
+                .iter()
+                .filter(|(_, d)| d.get_slug() == device.get_slug())
+                .count()
+                + 1;
+            instances.push((format!("{}#{}", device.get_slug(), ordinal), &**device));
+        }
+
+        instances
+    }
+
+    /// The devices in the pool
+    pub fn devices(&self) -> impl Iterator<Item = &dyn SteelseriesDevice> {
+        self.devices.iter().map(|d| &**d)
     }
 
     /**
-     * Consumes `self` and returns a new `DevicePool` filtered with supported devices
-     * that are actually connected to the host
+     * Consumes `self` and returns a new `DevicePool` holding one device for each
+     * supported device instance actually connected to the host
      */
     pub fn sync(self) -> DevicePool {
-        let connected_devices: Vec<&hidapi::DeviceInfo> = self.api.device_list().collect();
+        log::debug!("{} device(s) in supported pool", self.devices.len());
+        let devices = self.connected_devices();
+
+        DevicePool {
+            backend: self.backend,
+            definitions: self.definitions,
+            devices,
+        }
+    }
+
+    /// Re-enumerates the devices connected to the host, keeping a synced pool up to date.
+    /// Returns what changed since the last time the pool was synced or refreshed.
+    pub fn refresh(&mut self) -> Result<Vec<PoolEvent>> {
+        self.backend.refresh()?;
+        let devices = self.connected_devices();
+        let mut events = vec![];
+
+        let is_in = |devices: &[Box<dyn SteelseriesDevice>], device: &dyn SteelseriesDevice| {
+            devices
+                .iter()
+                .any(|d| d.get_slug() == device.get_slug() && d.get_instance() == device.get_instance())
+        };
+
+        for device in self.devices.iter().filter(|d| !is_in(&devices, &***d)) {
+            events.push(PoolEvent::Removed(PoolEventDevice::from(&**device)));
+        }
+
+        for device in devices.iter().filter(|d| !is_in(&self.devices, &***d)) {
+            events.push(PoolEvent::Arrived(PoolEventDevice::from(&**device)));
+        }
+
+        self.devices = devices;
+        Ok(events)
+    }
+
+    /// Builds one device for each supported device instance connected to the host
+    fn connected_devices(&self) -> Vec<Box<dyn SteelseriesDevice>> {
+        let connected_devices = self.backend.device_list();
         let mut filtered_devices: Vec<Box<dyn SteelseriesDevice>> = vec![];
-        crate::OUTPUT.verbose(format!("{} device(s) in supported pool", self.devices.len()));
 
-        for device in self.devices {
-            crate::OUTPUT.verbose(format!(
+        for definition in self.definitions.iter() {
+            log::debug!(
                 "Searching for connected device {}:{}",
-                crate::utils::format_radix(device.get_vendor_id() as u32, 16),
-                crate::utils::format_radix(device.get_product_id() as u32, 16),
-            ));
-            if let Some(connected_device) = connected_devices.iter().find(|d| {
-                d.vendor_id() == device.get_vendor_id() && d.product_id() == device.get_product_id()
-            }) {
-                crate::OUTPUT.verbose(format!(
-                    "Found connected device {}:{}",
-                    crate::utils::format_radix(connected_device.get_vendor_id() as u32, 16),
-                    crate::utils::format_radix(connected_device.get_product_id() as u32, 16),
-                ));
-                filtered_devices.push(device);
+                crate::utils::format_radix(definition.vendor_id as u32, 16),
+                crate::utils::format_radix(definition.product_id as u32, 16),
+            );
+
+            for connected_device in instances_of(definition, &connected_devices) {
+                log::debug!(
+                    "Found connected device {}:{} at {}",
+                    crate::utils::format_radix(connected_device.vendor_id as u32, 16),
+                    crate::utils::format_radix(connected_device.product_id as u32, 16),
+                    connected_device.path,
+                );
+                if let Some(device) = crate::devices::from_definition(
+                    definition.clone(),
+                    Rc::clone(&self.backend),
+                    Some(connected_device.clone()),
+                ) {
+                    filtered_devices.push(device);
+                }
             }
         }
 
-        DevicePool {
-            api: &HIDAPI,
-            devices: filtered_devices,
+        filtered_devices
+    }
+}
+
+/// A change in the devices connected to the host, as seen by `DevicePool::refresh`
+#[derive(Debug, Clone, PartialEq)]
+pub enum PoolEvent {
+    Arrived(PoolEventDevice),
+    Removed(PoolEventDevice),
+}
+
+/// The device a `PoolEvent` is about
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolEventDevice {
+    pub name: String,
+    pub slug: String,
+    pub info: Option<HidDeviceInfo>,
+}
+
+impl From<&dyn SteelseriesDevice> for PoolEventDevice {
+    fn from(device: &dyn SteelseriesDevice) -> Self {
+        Self {
+            name: device.get_name().to_string(),
+            slug: device.get_slug().to_string(),
+            info: device.get_instance().cloned(),
+        }
+    }
+}
+
+/// The connected devices matching `definition`, one per physical device.
+/// Entries are interfaces of the same physical device when they share its USB port, or,
+/// when the port cannot be told from their path, a serial number: only the first one
+/// accepted by the definition is kept.
+fn instances_of<'a>(
+    definition: &DeviceDefinition,
+    connected_devices: &'a [HidDeviceInfo],
+) -> Vec<&'a HidDeviceInfo> {
+    let mut instances: Vec<&HidDeviceInfo> = vec![];
+    let mut known: Vec<String> = vec![];
+    for info in connected_devices.iter().filter(|d| definition.accepts(d)) {
+        let physical = physical_device(info.path.as_str())
+            .or_else(|| {
+                info.serial_number
+                    .as_ref()
+                    .filter(|s| !s.is_empty())
+                    .map(|s| format!("@{}", s))
+            })
+            .unwrap_or_else(|| info.path.clone());
+        if !known.contains(&physical) {
+            known.push(physical);
+            instances.push(info);
+        }
+    }
+
+    instances
+}
+
+/// Names the USB device a HID interface belongs to from the interface `path`, as given
+/// by the libusb backend of hidapi (`1-4.2:1.0` or `0001:0004:01`) or by hidraw, whose
+/// device is looked up in sysfs. `None` for other paths.
+fn physical_device(path: &str) -> Option<String> {
+    if let Some(name) = path.strip_prefix("/dev/") {
+        let interface = std::fs::canonicalize(format!("/sys/class/hidraw/{}/device", name))
+            .ok()?
+            .parent()?
+            .to_path_buf();
+        let interface_name = interface.file_name()?.to_str()?;
+        usb_device_of_interface(interface_name)?;
+        return Some(interface.parent()?.display().to_string());
+    }
+
+    usb_device_of_interface(path).map(String::from)
+}
+
+/// The part of the USB interface name `interface` naming its device
+fn usb_device_of_interface(interface: &str) -> Option<&str> {
+    let is_number = |s: &str, radix: u32| !s.is_empty() && s.chars().all(|c| c.is_digit(radix));
+
+    // bus-port.port:config.interface
+    if let Some((port, setting)) = interface.split_once(':') {
+        if let Some((config, number)) = setting.split_once('.') {
+            let (bus, ports) = port.split_once('-')?;
+            let valid = is_number(bus, 10)
+                && ports.split('.').all(|p| is_number(p, 10))
+                && is_number(config, 10)
+                && is_number(number, 10);
+            return valid.then(|| &interface[..interface.len() - number.len() - 1]);
+        }
+    }
+
+    // bus:address:interface
+    let parts: Vec<&str> = interface.split(':').collect();
+    match parts.as_slice() {
+        [bus, address, number] if [bus, address, number].iter().all(|p| is_number(p, 16)) => {
+            Some(&interface[..interface.len() - number.len() - 1])
+        }
+        _ => None,
+    }
+}
+
+/// Directories device definitions are loaded from, in loading order
+fn definition_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from(SYSTEM_DEFINITIONS_DIR)];
+    if let Some(config_dir) = crate::utils::config_dir() {
+        dirs.push(config_dir.join("devices"));
+    }
+
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::instances_of;
+    use crate::steelseries_core::{DeviceDefinition, HidDeviceInfo};
+
+    fn sensei_ten(path: &str, serial: Option<&str>) -> HidDeviceInfo {
+        let mut info = HidDeviceInfo::new(0x1038, 0x1832, path).with_interface(1);
+        info.serial_number = serial.map(String::from);
+        info
+    }
+
+    #[test]
+    fn one_instance_per_physical_device() {
+        let arctis =
+            DeviceDefinition::from_toml(include_str!("../devices/definitions/arctis5.toml"))
+                .unwrap();
+        let interfaces: Vec<HidDeviceInfo> = (0..4)
+            .map(|i| {
+                HidDeviceInfo::new(
+                    0x1038,
+                    0x12aa,
+                    format!("1-{}:1.{}", 1 + i / 2, i % 2).as_str(),
+                )
+                .with_interface(i % 2)
+            })
+            .collect();
+        let instances = instances_of(&arctis, &interfaces);
+        assert_eq!(instances, vec![&interfaces[0], &interfaces[2]]);
+
+        // Without a port in their paths, interfaces are told apart by serial number
+        let sensei =
+            DeviceDefinition::from_toml(include_str!("../devices/definitions/senseiten.toml"))
+                .unwrap();
+        let interfaces = vec![
+            sensei_ten("DevSrvsID:1", Some("")),
+            sensei_ten("DevSrvsID:2", Some("")),
+            sensei_ten("DevSrvsID:3", Some("0123")),
+            sensei_ten("DevSrvsID:4", Some("0123")),
+        ];
+        let instances = instances_of(&sensei, &interfaces);
+        assert_eq!(
+            instances,
+            vec![&interfaces[0], &interfaces[1], &interfaces[2]]
+        );
+    }
+
+    #[cfg(feature = "mice")]
+    #[test]
+    fn find_by_spec() {
+        use super::DevicePool;
+        use crate::steelseries_core::{Error, MemoryBackend};
+        use std::rc::Rc;
+
+        let sensei =
+            DeviceDefinition::from_toml(include_str!("../devices/definitions/senseiten.toml"))
+                .unwrap();
+        let backend = MemoryBackend::new()
+            .with_info(sensei_ten("1-1:1.1", None))
+            .with_info(sensei_ten("1-2:1.1", Some("0123")));
+        let pool = DevicePool::with_definitions(Rc::new(backend), vec![sensei]).sync();
+        let path = |spec: &str| {
+            pool.find(spec)
+                .and_then(|d| d.get_instance())
+                .map(|i| i.path.clone())
+        };
+
+        assert_eq!(path("senseiten#1").as_deref(), Some("1-1:1.1"));
+        assert_eq!(path("senseiten#2").as_deref(), Some("1-2:1.1"));
+        assert_eq!(path("senseiten#3"), None);
+        assert_eq!(path("senseiten#0"), None);
+        assert_eq!(path("senseiten@0123").as_deref(), Some("1-2:1.1"));
+        assert_eq!(path("senseiten@4567"), None);
+        assert_eq!(path("1-2:1.1").as_deref(), Some("1-2:1.1"));
+        assert!(path("1038:1832").is_some());
+        assert_eq!(path("1038:0001"), None);
+        assert_eq!(path("senseiten").as_deref(), Some("1-1:1.1"));
+        assert_eq!(path("arctis5"), None);
+
+        // Mistyped specifications are told apart from devices that are not connected
+        for invalid in ["1-3:1.1", "1038:", "10380:1832", "+38:1832", "senseiten#x", "#0"] {
+            assert!(
+                matches!(pool.lookup(invalid), Err(Error::InvalidSpec(_))),
+                "{} was accepted",
+                invalid
+            );
+        }
+        for missing in ["1038:12aa", "senseiten#3", "senseiten@4567", "arctis5"] {
+            assert!(
+                matches!(pool.lookup(missing), Err(Error::NoDevice)),
+                "{} was found",
+                missing
+            );
         }
     }
 }
@@ -0,0 +1,332 @@
+use super::{DeviceProperty, PropertyValue, Result, SteelseriesDevice, TaskOptions, ToCode};
+
+/// A batch of property changes, possibly spanning several devices, applied all
+/// together or not at all.
+///
+/// The current value of every property is read first, and the batch is refused with
+/// `Error::Unsupported` if one of them can not be read back. Changes are then sent in
+/// order without saving. If one of them fails, it is rolled back along with the ones
+/// already sent to the values read before, since a failed change may still have been
+/// partly applied. When saving is requested, each device is saved once every change
+/// went through.
+#[derive(Default)]
+pub struct Transaction<'a> {
+    changes: Vec<Change<'a>>,
+}
+
+struct Change<'a> {
+    device: &'a dyn SteelseriesDevice,
+    property: DeviceProperty,
+    value: PropertyValue,
+}
+
+impl<'a> Transaction<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues setting `property` of `device` to `value`
+    pub fn add(
+        &mut self,
+        device: &'a dyn SteelseriesDevice,
+        property: DeviceProperty,
+        value: PropertyValue,
+    ) {
+        self.changes.push(Change {
+            device,
+            property,
+            value,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Applies every change. On failure, the devices are left as they were and the
+    /// error of the failed change is returned.
+    pub fn apply(&self, options: &TaskOptions) -> Result<()> {
+        // Unsupported properties are caught before anything is written
+        if let Some(change) = self
+            .changes
+            .iter()
+            .find(|c| c.device.supports_capability(c.property).is_none())
+        {
+            log::warn!(
+                "{} does not support {}",
+                change.device.get_name(),
+                change.property.to_code()
+            );
+            return Err(super::Error::InvalidCapability);
+        }
+
+        let staged = TaskOptions {
+            save: false,
+            ..*options
+        };
+
+        // Every previous value is read before anything is written, as a change that
+        // could not be rolled back would break the all or nothing promise
+        let mut previous = vec![];
+        for change in self.changes.iter() {
+            if options.dry {
+                previous.push(None);
+                continue;
+            }
+            match change.device.read_property(change.property) {
+                Ok(value) => previous.push(Some(value)),
+                Err(e) => {
+                    log::warn!(
+                        "Cannot read {} of {} back, it could not be rolled back: {:?}",
+                        change.property.to_code(),
+                        change.device.get_slug(),
+                        e
+                    );
+                    return Err(super::Error::Unsupported);
+                }
+            }
+        }
+
+        let mut applied: Vec<(&Change, Option<PropertyValue>)> = vec![];
+        for (change, previous) in self.changes.iter().zip(previous) {
+            // The change counts as applied before it is sent, as it may fail halfway
+            applied.push((change, previous));
+            if let Err(e) =
+                change
+                    .device
+                    .change_property(change.property, change.value.clone(), &staged)
+            {
+                rollback(&applied, &[], &staged);
+                return Err(e);
+            }
+        }
+
+        if options.save {
+            let mut saved: Vec<&dyn SteelseriesDevice> = vec![];
+            for change in self.changes.iter() {
+                if saved.iter().any(|d| std::ptr::addr_eq(*d, change.device)) {
+                    continue;
+                }
+                if let Err(e) = change.device.save(options) {
+                    rollback(&applied, &saved, &staged);
+                    return Err(e);
+                }
+                saved.push(change.device);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Restores the previous values of `applied`, newest first, then saves the devices
+/// in `saved` again. Failures are logged since there is nothing left to fall back to.
+fn rollback(
+    applied: &[(&Change, Option<PropertyValue>)],
+    saved: &[&dyn SteelseriesDevice],
+    options: &TaskOptions,
+) {
+    for (change, previous) in applied.iter().rev() {
+        if let Some(previous) = previous {
+            log::debug!(
+                "Rolling back {} of {} to {}",
+                change.property.to_code(),
+                change.device.get_slug(),
+                previous
+            );
+            if let Err(e) =
+                change
+                    .device
+                    .change_property(change.property, previous.clone(), options)
+            {
+                log::warn!(
+                    "Cannot roll back {} of {}: {:?}",
+                    change.property.to_code(),
+                    change.device.get_slug(),
+                    e
+                );
+            }
+        }
+    }
+
+    for device in saved {
+        if let Err(e) = device.save(options) {
+            log::warn!(
+                "Cannot save {} after rolling back: {:?}",
+                device.get_slug(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap};
+
+    use super::Transaction;
+    use crate::steelseries_core::{
+        Backend, Color, DeviceProfileValue, DeviceProperty, Error, MemoryBackend, PropertyValue,
+        Result, SteelseriesDevice, TaskOptions,
+    };
+
+    /// A device keeping its values in memory, failing to set `broken`
+    struct FakeDevice {
+        capabilities: Vec<DeviceProperty>,
+        values: RefCell<Vec<(DeviceProperty, PropertyValue)>>,
+        broken: Option<DeviceProperty>,
+        backend: MemoryBackend,
+    }
+
+    impl FakeDevice {
+        fn new(broken: Option<DeviceProperty>) -> Self {
+            let capabilities = vec![DeviceProperty::LedColor, DeviceProperty::WheelLedColor];
+            let values = capabilities
+                .iter()
+                .map(|c| (*c, PropertyValue::Color(Color::from((0x00, 0x00, 0x00)))))
+                .collect();
+            Self {
+                capabilities,
+                values: RefCell::new(values),
+                broken,
+                backend: MemoryBackend::new(),
+            }
+        }
+    }
+
+    impl SteelseriesDevice for FakeDevice {
+        fn enumerate_capabilities(&self) -> std::slice::Iter<'_, DeviceProperty> {
+            self.capabilities.iter()
+        }
+
+        fn get_name(&self) -> &str {
+            "Fake"
+        }
+
+        fn get_slug(&self) -> &str {
+            "fake"
+        }
+
+        fn change_property(
+            &self,
+            property: DeviceProperty,
+            value: PropertyValue,
+            _options: &TaskOptions,
+        ) -> Result<()> {
+            if self.broken == Some(property) {
+                return Err(Error::UsbComm);
+            }
+            let mut values = self.values.borrow_mut();
+            values.retain(|(p, _)| *p != property);
+            values.push((property, value));
+            Ok(())
+        }
+
+        fn read_property(&self, property: DeviceProperty) -> Result<PropertyValue> {
+            self.values
+                .borrow()
+                .iter()
+                .find(|(p, _)| *p == property)
+                .map(|(_, v)| v.clone())
+                .ok_or(Error::Unsupported)
+        }
+
+        fn get_vendor_id(&self) -> u16 {
+            0x1038
+        }
+
+        fn get_product_id(&self) -> u16 {
+            0x0000
+        }
+
+        fn get_profile(&self) -> Option<&HashMap<String, DeviceProfileValue>> {
+            None
+        }
+
+        fn get_backend(&self) -> &dyn Backend {
+            &self.backend
+        }
+    }
+
+    #[test]
+    fn failed_change_rolls_back() {
+        let device = FakeDevice::new(Some(DeviceProperty::WheelLedColor));
+        let red = PropertyValue::Color(Color::from((0xff, 0x00, 0x00)));
+        let mut transaction = Transaction::new();
+        transaction.add(&device, DeviceProperty::LedColor, red.clone());
+        transaction.add(&device, DeviceProperty::WheelLedColor, red.clone());
+
+        let result = transaction.apply(&TaskOptions::default());
+        assert!(matches!(result, Err(Error::UsbComm)));
+        assert_ne!(device.read_property(DeviceProperty::LedColor).unwrap(), red);
+
+        let working = FakeDevice::new(None);
+        let mut transaction = Transaction::new();
+        transaction.add(&working, DeviceProperty::LedColor, red.clone());
+        transaction.add(&working, DeviceProperty::WheelLedColor, red.clone());
+        transaction.apply(&TaskOptions::default()).unwrap();
+        assert_eq!(
+            working
+                .read_property(DeviceProperty::WheelLedColor)
+                .unwrap(),
+            red
+        );
+    }
+
+    #[test]
+    fn unreadable_value_refuses_batch() {
+        let device = FakeDevice::new(None);
+        device
+            .values
+            .borrow_mut()
+            .retain(|(p, _)| *p != DeviceProperty::WheelLedColor);
+        let red = PropertyValue::Color(Color::from((0xff, 0x00, 0x00)));
+        let mut transaction = Transaction::new();
+        transaction.add(&device, DeviceProperty::LedColor, red.clone());
+        transaction.add(&device, DeviceProperty::WheelLedColor, red.clone());
+
+        let result = transaction.apply(&TaskOptions::default());
+        assert!(matches!(result, Err(Error::Unsupported)));
+        assert_ne!(device.read_property(DeviceProperty::LedColor).unwrap(), red);
+    }
+
+    /// Both cups of the headset are set by `hc`, the right one failing after the left one
+    /// changed
+    #[cfg(feature = "headsets")]
+    #[test]
+    fn partly_applied_change_rolls_back() {
+        use crate::devices::ArctisFiveHeadphones;
+        use crate::steelseries_core::{HidDeviceInfo, Packet};
+        use std::rc::Rc;
+
+        let backend =
+            MemoryBackend::new().with_info(HidDeviceInfo::new(0x1038, 0x12aa, "memory:0"));
+        let transport = backend.transport().clone();
+        let headset = ArctisFiveHeadphones::new(Rc::new(backend));
+        let green = PropertyValue::Color(Color::from((0x00, 0xff, 0x00)));
+        let options = TaskOptions::default();
+        headset
+            .change_property(DeviceProperty::LeftHeadphoneLedColor, green, &options)
+            .unwrap();
+        let green = transport.sent().remove(0);
+        if let Packet::FeatureReport(report) = &green {
+            transport.push_reply(report);
+        }
+
+        transport.fail_after(1);
+        let mut transaction = Transaction::new();
+        let red = PropertyValue::Color(Color::from((0xff, 0x00, 0x00)));
+        transaction.add(&headset, DeviceProperty::HeadphonesColor, red);
+        assert!(matches!(transaction.apply(&options), Err(Error::UsbComm)));
+
+        // Green, then red on the left cup, then green on both cups again
+        let sent = transport.sent();
+        assert_eq!(sent.len(), 4);
+        assert_ne!(sent[1], green);
+        assert_eq!(sent[2..], [green.clone(), green]);
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    rc::Rc,
+};
+
+use super::{Error, Result};
+
+/// A single packet sent to a device through a `Transport`
+#[derive(Debug, PartialEq, Clone)]
+pub enum Packet {
+    Write(Vec<u8>),
+    FeatureReport(Vec<u8>),
+}
+
+/// The low level channel a `SteelseriesDeviceHandle` talks through.
+/// Implementors can be real HID devices or anything that behaves like one.
+pub trait Transport {
+    /// Writes an output report to the device, returning the number of bytes written
+    fn write(&self, buf: &[u8]) -> Result<usize>;
+
+    /// Sends a feature report to the device. The first byte of `buf` is the report id.
+    fn send_feature_report(&self, buf: &[u8]) -> Result<()>;
+
+    /// Reads a feature report from the device. The first byte of `buf` must be set
+    /// to the report id to read.
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize>;
+
+    /// Reads an input report, waiting at most `timeout` milliseconds (-1 to block)
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> Result<usize>;
+}
+
+#[cfg(feature = "hidapi-backend")]
+impl Transport for hidapi::HidDevice {
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        Ok(hidapi::HidDevice::write(self, buf)?)
+    }
+
+    fn send_feature_report(&self, buf: &[u8]) -> Result<()> {
+        Ok(hidapi::HidDevice::send_feature_report(self, buf)?)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        Ok(hidapi::HidDevice::get_feature_report(self, buf)?)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> Result<usize> {
+        Ok(hidapi::HidDevice::read_timeout(self, buf, timeout)?)
+    }
+}
+
+/// An in-memory transport that records every packet sent to it and answers
+/// reads with replies queued in advance. Clones share the same state, so a clone
+/// can be kept around to inspect the traffic after the device handle is dropped.
+#[derive(Clone, Default)]
+pub struct MemoryTransport {
+    sent: Rc<RefCell<Vec<Packet>>>,
+    replies: Rc<RefCell<VecDeque<Vec<u8>>>>,
+    /// How many packets go through before one fails, if any should
+    failing: Rc<Cell<Option<usize>>>,
+}
+
+impl MemoryTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a reply to be returned by the next read
+    pub fn push_reply(&self, reply: &[u8]) {
+        self.replies.borrow_mut().push_back(reply.to_vec());
+    }
+
+    /// All the packets sent so far, oldest first
+    pub fn sent(&self) -> Vec<Packet> {
+        self.sent.borrow().clone()
+    }
+
+    /// Makes the packet sent after the next `accepted` ones fail, as a device rejecting
+    /// it or unplugged would. The packets sent after the failed one go through again.
+    pub fn fail_after(&self, accepted: usize) {
+        self.failing.set(Some(accepted));
+    }
+
+    fn accept(&self) -> Result<()> {
+        match self.failing.get() {
+            Some(0) => {
+                self.failing.set(None);
+                Err(Error::UsbComm)
+            }
+            Some(accepted) => {
+                self.failing.set(Some(accepted - 1));
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn pop_reply(&self, buf: &mut [u8]) -> Result<usize> {
+        match self.replies.borrow_mut().pop_front() {
+            Some(reply) => {
+                let len = reply.len().min(buf.len());
+                buf[..len].copy_from_slice(&reply[..len]);
+                Ok(len)
+            }
+            None => Err(Error::UsbComm),
+        }
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        self.accept()?;
+        self.sent.borrow_mut().push(Packet::Write(buf.to_vec()));
+        Ok(buf.len())
+    }
+
+    fn send_feature_report(&self, buf: &[u8]) -> Result<()> {
+        self.accept()?;
+        self.sent
+            .borrow_mut()
+            .push(Packet::FeatureReport(buf.to_vec()));
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        self.pop_reply(buf)
+    }
+
+    /// Returns the next queued reply, or nothing read as if the timeout had expired
+    fn read_timeout(&self, buf: &mut [u8], _timeout: i32) -> Result<usize> {
+        if self.replies.borrow().is_empty() {
+            return Ok(0);
+        }
+        self.pop_reply(buf)
+    }
+}
@@ -0,0 +1,83 @@
+use std::{fmt::Write, fs, path::Path};
+
+use crate::steelseries_core::{support::DevicePool, Error, Result};
+
+/// Where `--install` puts the rules when no path is given. Rules tagging devices with
+/// `uaccess` must be read before systemd's `73-seat-late.rules`.
+pub const DEFAULT_RULES_PATH: &str = "/etc/udev/rules.d/70-steelcli.rules";
+
+/// Builds a udev rules file giving non-root users access to every supported device.
+/// Access is granted to the user logged in at the seat through `uaccess`, or to the
+/// members of `group` when given, which must be a plain group name.
+pub fn rules(pool: &DevicePool, group: Option<&str>) -> Result<String> {
+    let access = match group {
+        Some(group) if is_group_name(group) => format!("MODE=\"0660\", GROUP=\"{}\"", group),
+        Some(group) => {
+            crate::OUTPUT.error(
+                format!(
+                    "Invalid group name {}, expected lowercase letters, digits, - and _",
+                    group
+                )
+                .as_str(),
+            );
+            return Err(Error::Generic("Invalid group name"));
+        }
+        None => String::from("TAG+=\"uaccess\""),
+    };
+
+    let mut rules = String::from("# Generated by steelcli udev-rules\n");
+    let mut seen: Vec<(u16, u16)> = vec![];
+    for device in pool.devices() {
+        let id = (device.get_vendor_id(), device.get_product_id());
+        if seen.contains(&id) {
+            continue;
+        }
+        seen.push(id);
+
+        // hidraw nodes are used by the hidraw backend, usb ones by the libusb backend
+        let _ = writeln!(rules, "\n# {}", device.get_name());
+        for subsystem in ["hidraw", "usb"] {
+            let _ = writeln!(
+                rules,
+                "SUBSYSTEM==\"{}\", ATTRS{{idVendor}}==\"{:04x}\", ATTRS{{idProduct}}==\"{:04x}\", {}",
+                subsystem, id.0, id.1, access
+            );
+        }
+    }
+
+    Ok(rules)
+}
+
+/// Checks that `group` is a group name as accepted by `groupadd`, `[a-z_][a-z0-9_-]*`,
+/// which leaves nothing to escape in a rules file
+fn is_group_name(group: &str) -> bool {
+    let mut chars = group.chars();
+    group.len() <= 32
+        && chars
+            .next()
+            .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+/// Prints the rules, or writes them to `install` when given
+pub fn udev_rules(pool: &DevicePool, group: Option<&str>, install: Option<&str>) -> Result<()> {
+    let rules = rules(pool, group)?;
+    match install {
+        Some(path) => {
+            if let Err(e) = fs::write(Path::new(path), rules) {
+                crate::OUTPUT.error(format!("Cannot write {}: {}", path, e).as_str());
+                if e.kind() == std::io::ErrorKind::PermissionDenied {
+                    crate::OUTPUT.log("Installing rules requires root, try again with --escalate");
+                }
+                return Err(e.into());
+            }
+            crate::OUTPUT.success(format!("Installed {}", path).as_str());
+            crate::OUTPUT.log(
+                "Run `udevadm control --reload-rules && udevadm trigger` and reconnect your devices",
+            );
+        }
+        None => print!("{}", rules),
+    }
+
+    Ok(())
+}
@@ -1,36 +1,4 @@
-use std::ops::{Div, Rem};
-
-pub trait ToStringRadix
-where
-    Self: Copy + Into<u32> + Rem<u32> + Div<Output = u32> + PartialEq<u32>,
-{
-    fn to_string_radix(&self, radix: u32) -> String {
-        let mut result = vec![];
-        let mut x: u32 = self.clone().into();
-
-        loop {
-            let m: u32 = x % radix;
-            x = x / radix;
-
-            // will panic if you use a bad radix (< 2 or > 36).
-            result.push(std::char::from_digit(m, radix).unwrap());
-            if x == 0 {
-                break;
-            }
-        }
-        result.into_iter().rev().collect()
-    }
-}
-
-macro_rules! to_string_radix_impl {
-    ( $( $t:ty ),* ) => {
-        $(
-            impl ToStringRadix for $t {}
-        )*
-    };
-}
-
-to_string_radix_impl!(u32);
+use std::{env, path::PathBuf, time::Duration};
 
 /// Turns a number `x` into a string representation with a given `radix`
 /// 
@@ -41,7 +9,7 @@ pub fn format_radix(mut x: u32, radix: u32) -> String {
 
     loop {
         let m = x % radix;
-        x = x / radix;
+        x /= radix;
 
         // will panic if you use a bad radix (< 2 or > 36).
         result.push(std::char::from_digit(m, radix).unwrap());
@@ -52,15 +20,58 @@ pub fn format_radix(mut x: u32, radix: u32) -> String {
     result.into_iter().rev().collect()
 }
 
-/// Parses a device id in the form of `vendor_id:product_id`, where both ids are base16 numbers
-pub fn parse_device_id(device_str: &str) -> (u16, u16) {
-    if !device_str.contains(":") {
-        return (0, 0);
+/// Formats `bytes` as a lowercase hex string, two digits per byte
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Parses a string of hex digit pairs back into bytes
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// The steelcli configuration directory, usually `~/.config/steelcli`
+pub fn config_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("steelcli")),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("steelcli")),
     }
+}
 
-    let parts = device_str.split(":").collect::<Vec<&str>>(); // eg: 1038:12aa
-    let vid = u16::from_str_radix(parts[0], 16).unwrap_or(0);
-    let pid = u16::from_str_radix(parts[1], 16).unwrap_or(0);
+/// Parses a duration such as `500ms`, `3s` or `1m`. Bare numbers are milliseconds.
+pub fn parse_duration(raw: &str) -> Option<Duration> {
+    let raw = raw.trim();
+    let split = raw
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(raw.len());
+    let (amount, unit) = raw.split_at(split);
+    let amount = amount.parse::<f64>().ok()?;
+    let millis = match unit.trim() {
+        "" | "ms" => amount,
+        "s" => amount * 1000.0,
+        "m" => amount * 60_000.0,
+        _ => return None,
+    };
+
+    Some(Duration::from_millis(millis as u64))
+}
+
+/// Parses a device id in the form of `vendor_id:product_id`, where both ids are base16 numbers.
+/// `None` when `device_str` is not such an id.
+pub fn parse_device_id(device_str: &str) -> Option<(u16, u16)> {
+    let (vid, pid) = device_str.split_once(':')?; // eg: 1038:12aa
+    let parse = |id: &str| {
+        Some(id)
+            .filter(|id| id.chars().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|id| u16::from_str_radix(id, 16).ok())
+    };
 
-    (vid, pid)
+    Some((parse(vid)?, parse(pid)?))
 }
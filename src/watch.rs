@@ -0,0 +1,31 @@
+use std::{thread, time::Duration};
+
+use colored::Colorize;
+
+use crate::steelseries_core::{
+    support::{DevicePool, PoolEvent, PoolEventDevice},
+    Result,
+};
+
+/// Polls the host for supported devices every `interval`, printing each arrival and
+/// removal. Devices already connected are printed first. Runs until interrupted.
+pub fn watch(mut pool: DevicePool, interval: Duration) -> Result<()> {
+    for (_, device) in pool.instances() {
+        print_event("present".normal(), &PoolEventDevice::from(device));
+    }
+
+    loop {
+        thread::sleep(interval);
+        for event in pool.refresh()? {
+            match event {
+                PoolEvent::Arrived(device) => print_event("arrived".green(), &device),
+                PoolEvent::Removed(device) => print_event("removed".red(), &device),
+            }
+        }
+    }
+}
+
+fn print_event(kind: colored::ColoredString, device: &PoolEventDevice) {
+    let path = device.info.as_ref().map(|i| i.path.as_str()).unwrap_or("-");
+    println!("{}\t{}\t{}\t{}", kind.bold(), device.slug, device.name, path);
+}